@@ -1,21 +1,21 @@
-mod puf;
 use blake2::{Blake2b512, Blake2bMac512, Digest};
 use blstrs_plus::{
     ff::Field,
     G1Affine, G1Projective, G2Affine, Scalar, elliptic_curve::hash2curve::ExpandMsgXmd, group::prime::PrimeCurveAffine, pairing,
 };
 use hmac::Mac;
-use puf::Puf;
+use puf::{MajorityPuf, PufBackend, SimPuf, TcpPuf};
 use rand::RngCore;
 use rug::{Integer, integer::Order};
 use sha2::Sha256;
+use std::sync::Arc;
 
 const TAG: &[u8] = b"BLS_SIG_BLS12381G1_XMD:BLAKE2b-512_SSWU_RO_NUL_";
 type HmacBlake2b = Blake2bMac512;
 
 #[tokio::main]
 async fn main() {
-    let puf = Puf::new_with_pool_size(([127, 0, 0, 1], 12345), 8).await.unwrap();
+    let puf = TcpPuf::new_with_pool_size("127.0.0.1:12345", 8).await.unwrap();
     test_scalar_mul_point1();
     test_scalar_mul_point2();
     test_add_g1();
@@ -40,6 +40,9 @@ async fn main() {
         test_secret_sharing_recovery(n);
     }
     test_puf(&puf).await;
+    for noise in [0.01, 0.02, 0.05] {
+        test_puf_majority(noise).await;
+    }
 }
 
 fn test_add_g1() {
@@ -264,14 +267,34 @@ fn test_secret_sharing_recovery(n: usize) {
     println!("Secret sharing recovery over BLS scalar field with n={n} took: {:?}", t.elapsed());
 }
 
-async fn test_puf(puf: &Puf) {
+async fn test_puf(puf: &TcpPuf) {
     let c = rand::random::<[u8; 12]>();
     let c_hex = hex::encode(c);
     let t = std::time::Instant::now();
-    let _r = puf.calculate(c_hex).await.unwrap();
+    let _r = puf.calculate(&c_hex).await.unwrap();
     println!("PUF calculation took: {:?}", t.elapsed());
 }
 
+/// Share of challenges whose majority-voted response matches the noise-free one on a simulated PUF
+/// with bit-flip rate `noise`. This is the PUF alone; the share of accepted authentications is
+/// measured end to end with `uav --auth-trials`, see the README.
+async fn test_puf_majority(noise: f64) {
+    const CHALLENGES: usize = 1000;
+    let exact = SimPuf::new(1, 1, 0.0).unwrap();
+    for reads in [1, 3, 5, 7] {
+        let puf = MajorityPuf::new(Arc::new(SimPuf::new(1, 1, noise).unwrap()), reads).unwrap();
+        let mut ok = 0;
+        for _ in 0..CHALLENGES {
+            let c = hex::encode(rand::random::<[u8; 12]>());
+            ok += (puf.calculate(&c).await.unwrap() == exact.calculate(&c).await.unwrap()) as usize;
+        }
+        println!(
+            "PUF noise {noise}, {reads} reads: {:.1}% exact responses",
+            100.0 * ok as f64 / CHALLENGES as f64
+        );
+    }
+}
+
 fn random_nonzero_integer_256() -> Integer {
     loop {
         let bytes = rand::random::<[u8; 32]>();
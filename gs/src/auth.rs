@@ -1,13 +1,14 @@
-use crate::{mem, UavInfo, GS_CONFIG, TAG, UAV_LIST};
+use crate::{mem, UavInfo, GS_CONFIG, TAG, UAV_LIST, UAV_PREPARED};
 use blake2::{Blake2b512, Digest};
 use blstrs_plus::{
     elliptic_curve::hash2curve::ExpandMsgXmd, group::prime::PrimeCurveAffine, pairing, G1Affine, G1Projective, G2Affine, Scalar,
 };
 use chrono::Utc;
 use hex::ToHex;
+use rayon::prelude::*;
 use rpc::{GsAuthRequest, GsAuthResponseStruct, TaRpcClient};
 use tarpc::context;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use utils::{abbreviate_key_default, decrypt_aes128_gcm};
 
 #[allow(clippy::missing_transmute_annotations)]
@@ -75,18 +76,14 @@ pub(crate) async fn auth(client: &TaRpcClient, ta_pk1: &G1Affine, ta_pk2: &G2Aff
 
     let data = serde_json::from_slice::<Vec<GsAuthResponseStruct>>(&data)?;
     debug!("Decrypted UAV data: {:?}", data);
-    for uav in data {
-        UAV_LIST.0.insert(
-            uav.uid.clone(),
-            UavInfo {
-                uid: uav.uid,
-                pk: G2Affine::from_compressed_hex(&uav.pk_u).expect("Invalid UAV public key"),
-                c: uav.c,
-                z: uav.z,
-                p: uav.p,
-            },
-        );
-    }
+    // preparing pk_u dominates loading, so spread it over all cores
+    data.into_par_iter().for_each(|uav| match UavInfo::decode(&uav) {
+        Ok(info) => {
+            UAV_PREPARED.refresh(&uav.uid, &info.pk);
+            UAV_LIST.0.insert(uav.uid, info);
+        }
+        Err(e) => warn!("Skipping UAV {}: {}", abbreviate_key_default(&uav.uid), e),
+    });
 
     info!("Received UAV list size: {}", UAV_LIST.0.len());
     mem::log_uav_storage_stats("uav_list_updated");
@@ -0,0 +1,193 @@
+use blstrs_plus::{group::Group, G1Affine, G1Projective, G2Prepared, Scalar};
+use rpc::BatchAuthMetrics;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tracing::warn;
+use utils::{g2_generator_prepared, pairing_product_is_identity};
+
+static BATCHES: AtomicU64 = AtomicU64::new(0);
+static FALLBACKS: AtomicU64 = AtomicU64::new(0);
+static SUB_CHECKS: AtomicU64 = AtomicU64::new(0);
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static REJECTED: AtomicU64 = AtomicU64::new(0);
+static BUSY: AtomicU64 = AtomicU64::new(0);
+
+/// One UAV's term of the batch equation `e(sigma_i + z_i, g2) = e(h_i, pk_i) · e(g_r_i, pk_t)`.
+pub(crate) struct BatchItem {
+    pub sigma_z: G1Affine,
+    pub h: G1Affine,
+    pub pk: Arc<G2Prepared>,
+    pub g_r: G1Affine,
+}
+
+/// Aggregate check of every item in `items` under fresh random 64-bit weights.
+///
+/// With unit weights, invalid signatures whose errors cancel in the sum would pass; random
+/// small exponents make that succeed with probability about 2^-64. A single item needs no weight.
+fn verify(items: &[BatchItem], pk_t: &G2Prepared) -> bool {
+    if let [item] = items {
+        return verify_one(item, pk_t);
+    }
+    let weights = items.iter().map(|_| weight()).collect::<Vec<_>>();
+    verify_weighted(items, &weights, pk_t)
+}
+
+/// Uniform non-zero 64-bit weight; a zero weight would drop its item from the check.
+fn weight() -> Scalar {
+    loop {
+        let w = rand::random::<u64>();
+        if w != 0 {
+            return Scalar::from(w);
+        }
+    }
+}
+
+/// Check `e(-(sigma + z), g2) · e(h, pk) · e(g_r, pk_t) = 1` with one multi-pairing.
+fn verify_one(item: &BatchItem, pk_t: &G2Prepared) -> bool {
+    let neg_sigma_z = -item.sigma_z;
+    pairing_product_is_identity(&[(&neg_sigma_z, g2_generator_prepared()), (&item.h, &item.pk), (&item.g_r, pk_t)])
+}
+
+/// Check `e(-sum w_i (sigma_i + z_i), g2) · prod e(w_i h_i, pk_i) · e(sum w_i g_r_i, pk_t) = 1`.
+fn verify_weighted(items: &[BatchItem], weights: &[Scalar], pk_t: &G2Prepared) -> bool {
+    let sigma_z = items
+        .iter()
+        .zip(weights)
+        .map(|(item, w)| item.sigma_z * w)
+        .fold(G1Projective::identity(), |acc, s| acc + s);
+    let neg_sigma_z = G1Affine::from(-sigma_z);
+
+    // every g_r_i is paired with pk_t, so their weighted sum needs a single Miller loop term
+    let g_r = items
+        .iter()
+        .zip(weights)
+        .map(|(item, w)| item.g_r * w)
+        .fold(G1Projective::identity(), |acc, g| acc + g);
+    let g_r = G1Affine::from(g_r);
+    let h_is = items
+        .iter()
+        .zip(weights)
+        .map(|(item, w)| G1Affine::from(item.h * w))
+        .collect::<Vec<_>>();
+    let mut terms = h_is.iter().zip(items.iter().map(|item| &*item.pk)).collect::<Vec<_>>();
+    terms.push((&g_r, pk_t));
+    terms.push((&neg_sigma_z, g2_generator_prepared()));
+    pairing_product_is_identity(&terms)
+}
+
+/// Mark the valid items of a batch known to contain at least one invalid item by recursive bisection.
+fn isolate(items: &[BatchItem], pk_t: &G2Prepared, valid: &mut [bool]) {
+    if items.len() == 1 {
+        return;
+    }
+    let mid = items.len() / 2;
+    let (left, right) = items.split_at(mid);
+    let (valid_left, valid_right) = valid.split_at_mut(mid);
+
+    SUB_CHECKS.fetch_add(2, Ordering::Relaxed);
+    let (left_ok, right_ok) = rayon::join(|| verify(left, pk_t), || verify(right, pk_t));
+    let isolate_half = |ok: bool, items: &[BatchItem], valid: &mut [bool]| {
+        if ok {
+            valid.fill(true);
+        } else {
+            isolate(items, pk_t, valid);
+        }
+    };
+    rayon::join(
+        || isolate_half(left_ok, left, valid_left),
+        || isolate_half(right_ok, right, valid_right),
+    );
+}
+
+/// Verify `items` with one aggregate check, falling back to bisection when it fails.
+///
+/// Returns whether each item is valid, in the order of `items`.
+pub(crate) fn verify_batch(items: &[BatchItem], pk_t: &G2Prepared) -> Vec<bool> {
+    BATCHES.fetch_add(1, Ordering::Relaxed);
+    REQUESTS.fetch_add(items.len() as u64, Ordering::Relaxed);
+    if items.is_empty() || verify(items, pk_t) {
+        return vec![true; items.len()];
+    }
+
+    FALLBACKS.fetch_add(1, Ordering::Relaxed);
+    let mut valid = vec![false; items.len()];
+    isolate(items, pk_t, &mut valid);
+    let rejected = valid.iter().filter(|ok| !**ok).count();
+    REJECTED.fetch_add(rejected as u64, Ordering::Relaxed);
+    warn!(
+        "Batch aggregate check failed, bisection isolated {}/{} invalid signatures",
+        rejected,
+        items.len()
+    );
+    valid
+}
+
+/// Record requests rejected before the pairing check, e.g. stale or malformed ones.
+pub(crate) fn record_rejected(count: usize) {
+    REQUESTS.fetch_add(count as u64, Ordering::Relaxed);
+    REJECTED.fetch_add(count as u64, Ordering::Relaxed);
+}
+
+/// Record requests refused because the verification queue was full.
+pub(crate) fn record_busy(count: usize) {
+    BUSY.fetch_add(count as u64, Ordering::Relaxed);
+}
+
+pub(crate) fn metrics() -> BatchAuthMetrics {
+    BatchAuthMetrics {
+        batches: BATCHES.load(Ordering::Relaxed),
+        fallbacks: FALLBACKS.load(Ordering::Relaxed),
+        sub_checks: SUB_CHECKS.load(Ordering::Relaxed),
+        requests: REQUESTS.load(Ordering::Relaxed),
+        rejected: REJECTED.load(Ordering::Relaxed),
+        busy: BUSY.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs_plus::{ff::Field, group::prime::PrimeCurveAffine, G2Affine};
+
+    fn random_scalar() -> Scalar {
+        Scalar::random(rand::thread_rng())
+    }
+
+    /// Two individually valid items under TA key `sk_t`.
+    fn valid_pair(sk_t: Scalar) -> Vec<BatchItem> {
+        (0..2)
+            .map(|_| {
+                let sk = random_scalar();
+                let h = G1Affine::from(G1Projective::generator() * random_scalar());
+                let g_r = G1Affine::from(G1Projective::generator() * random_scalar());
+                BatchItem {
+                    sigma_z: (h * sk + g_r * sk_t).into(),
+                    h,
+                    pk: Arc::new(G2Prepared::from(G2Affine::from(G2Affine::generator() * sk))),
+                    g_r,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cancelling_pair_rejected() {
+        let sk_t = random_scalar();
+        let pk_t = G2Prepared::from(G2Affine::from(G2Affine::generator() * sk_t));
+        let mut items = valid_pair(sk_t);
+        assert!(verify(&items, &pk_t));
+
+        // shift the first signature by d and the second by -d
+        let d = G1Projective::generator() * random_scalar();
+        items[0].sigma_z = (G1Projective::from(items[0].sigma_z) + d).into();
+        items[1].sigma_z = (G1Projective::from(items[1].sigma_z) - d).into();
+
+        assert!(verify_weighted(&items, &[Scalar::ONE, Scalar::ONE], &pk_t));
+        assert!(!verify(&items, &pk_t));
+        assert!(!verify(&items[..1], &pk_t));
+        assert!(!verify(&items[1..], &pk_t));
+        assert_eq!(verify_batch(&items, &pk_t), vec![false, false]);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{pool::VerifyPool, rpc_impl::verify_phase2_on};
+use blstrs_plus::G2Prepared;
+use rpc::{BatchAuthStatus, UavAuthRequest2};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+use tracing::debug;
+
+/// Upper bound of requests verified together in one coalesced batch.
+const MAX_COALESCED_BATCH: usize = 256;
+
+struct Pending {
+    req: UavAuthRequest2,
+    reply: oneshot::Sender<BatchAuthStatus>,
+}
+
+/// Queues concurrent single-UAV phase 2 requests and verifies them together.
+///
+/// The first request opens a window of `window`; everything arriving before it closes, up to
+/// [`MAX_COALESCED_BATCH`], goes through [`crate::batch::verify_batch`], which falls back to checking
+/// sub-batches down to individual requests when the aggregate check fails.
+#[derive(Debug, Clone)]
+pub(crate) struct AuthCoalescer {
+    tx: mpsc::Sender<Pending>,
+}
+
+impl AuthCoalescer {
+    /// Start the collecting task on the current tokio runtime; batches are verified on `pool`.
+    pub(crate) fn spawn(pk_t: Arc<G2Prepared>, window: Duration, pool: VerifyPool) -> Self {
+        let (tx, rx) = mpsc::channel(MAX_COALESCED_BATCH * 4);
+        tokio::spawn(run(rx, pk_t, window, pool));
+        Self { tx }
+    }
+
+    /// Verify `req` as part of the next batch.
+    pub(crate) async fn verify(&self, req: UavAuthRequest2) -> BatchAuthStatus {
+        let (reply, rx) = oneshot::channel();
+        if self.tx.send(Pending { req, reply }).await.is_err() {
+            return BatchAuthStatus::Busy;
+        }
+        rx.await.unwrap_or(BatchAuthStatus::Busy)
+    }
+}
+
+async fn run(mut rx: mpsc::Receiver<Pending>, pk_t: Arc<G2Prepared>, window: Duration, pool: VerifyPool) {
+    while let Some(first) = rx.recv().await {
+        let deadline = Instant::now() + window;
+        let mut pending = vec![first];
+        while pending.len() < MAX_COALESCED_BATCH {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(p)) => pending.push(p),
+                _ => break,
+            }
+        }
+        debug!("Coalesced {} phase 2 requests into one batch", pending.len());
+
+        // keep collecting the next batch while this one waits for the pool
+        let (pool, pk_t) = (pool.clone(), pk_t.clone());
+        tokio::spawn(async move {
+            let (reqs, replies): (Vec<_>, Vec<_>) = pending.into_iter().map(|p| (p.req, p.reply)).unzip();
+            let statuses = verify_phase2_on(&pool, pk_t, reqs).await;
+            for (reply, status) in replies.into_iter().zip(statuses) {
+                let _ = reply.send(status);
+            }
+        });
+    }
+}
@@ -0,0 +1,523 @@
+use crate::{policy::GroupPolicy, GSConfig, UAV_LIST};
+use blstrs_plus::{elliptic_curve::hash2curve::ExpandMsgXmd, G1Projective};
+use dashmap::{
+    mapref::entry::{Entry, OccupiedEntry},
+    DashMap,
+};
+use hex::ToHex;
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use rpc::{GroupKeyMessage, UavCommResponse, GROUP_KEY_DST, MAX_QUEUED_GROUP_KEYS};
+use rug::{integer::Order, Integer};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
+use tracing::{info, warn};
+use utils::{abbreviate_key_default, key_check_value, CrtBackend, CrtGroup};
+
+/// Group key messages older than this many seconds are dropped from mailboxes.
+const GROUP_KEY_TTL: i64 = 300;
+
+/// A group key message together with the GS signature over it.
+#[derive(Debug)]
+pub(crate) struct SignedGroupKey {
+    pub msg: GroupKeyMessage,
+    pub sigma: String,
+}
+
+impl SignedGroupKey {
+    /// The copy of this message addressed to `uid`, carrying only its own challenge.
+    pub(crate) fn response_for(&self, uid: &str) -> Option<UavCommResponse> {
+        let c = UAV_LIST.0.get(uid)?.challenge();
+        Some(UavCommResponse {
+            msg: self.msg.clone(),
+            sigma: self.sigma.clone(),
+            c,
+        })
+    }
+}
+
+/// The message of the shard `uid` belongs to, addressed to `uid`.
+pub(crate) fn response_for(keys: &[Arc<SignedGroupKey>], uid: &str) -> Option<UavCommResponse> {
+    keys.iter().find(|key| key.msg.members.iter().any(|m| m == uid))?.response_for(uid)
+}
+
+/// Pending delivery of a shared group key message with the member's own challenge.
+struct Delivery {
+    key: Arc<SignedGroupKey>,
+    c: String,
+}
+
+/// Members of one CRT shard of a named group and their cached CRT state.
+#[derive(Debug, Clone)]
+pub(crate) struct Shard {
+    pub members: Vec<String>,
+    pub crt: CrtGroup,
+}
+
+/// A named group kept by the GS. Every membership change moves it to a new epoch with a fresh `k_d`.
+#[derive(Debug, Clone)]
+pub(crate) struct Group {
+    pub owner: String,
+    pub shards: Vec<Shard>,
+    pub shard_size: usize,
+    pub epoch: u64,
+}
+
+impl Group {
+    fn members(&self) -> impl Iterator<Item = &String> {
+        self.shards.iter().flat_map(|shard| &shard.members)
+    }
+
+    fn contains(&self, uid: &str) -> bool {
+        self.members().any(|m| m == uid)
+    }
+}
+
+lazy_static! {
+    static ref GROUP_MAILBOX: DashMap<String, VecDeque<Delivery>> = DashMap::new();
+    static ref GROUPS: DashMap<String, Group> = DashMap::new();
+    /// Last epoch of every dissolved group, a few bytes per name ever used.
+    static ref RETIRED_EPOCHS: DashMap<String, u64> = DashMap::new();
+}
+
+/// Queue `key` for member `uid` together with its PUF challenge `c`.
+fn deliver(uid: &str, key: &Arc<SignedGroupKey>, c: String) {
+    let mut mailbox = GROUP_MAILBOX.entry(uid.to_string()).or_default();
+    if mailbox.len() == MAX_QUEUED_GROUP_KEYS {
+        mailbox.pop_front();
+    }
+    mailbox.push_back(Delivery { key: key.clone(), c });
+}
+
+/// Take every unexpired group key message queued for `uid`.
+pub(crate) fn take(uid: &str) -> Vec<UavCommResponse> {
+    let t_now = chrono::Utc::now().timestamp();
+    let Some((_, mailbox)) = GROUP_MAILBOX.remove(uid) else {
+        return vec![];
+    };
+    mailbox
+        .into_iter()
+        .filter(|d| t_now - d.key.msg.t_g <= GROUP_KEY_TTL)
+        .map(|d| UavCommResponse {
+            msg: d.key.msg.clone(),
+            sigma: d.key.sigma.clone(),
+            c: d.c,
+        })
+        .collect()
+}
+
+/// PUF primes `p_i` of `members` as registered with the GS.
+pub(crate) fn member_primes(members: &[String]) -> Option<Vec<Integer>> {
+    members.iter().map(|uid| member_prime(uid)).collect()
+}
+
+fn member_prime(uid: &str) -> Option<Integer> {
+    let Some(uav) = UAV_LIST.0.get(uid) else {
+        warn!("UAV with uid {} not found", abbreviate_key_default(uid));
+        return None;
+    };
+    Some(uav.prime())
+}
+
+/// Split `members` into shards of at most `shard_size` and compute the `eta` of each with `backend`.
+pub(crate) fn shard_members(members: Vec<String>, shard_size: usize, backend: CrtBackend) -> Option<Vec<(Vec<String>, Integer)>> {
+    members
+        .par_chunks(shard_size.max(1))
+        .map(|chunk| Some((chunk.to_vec(), backend.build(member_primes(chunk)?))))
+        .collect()
+}
+
+/// Signed messages of one epoch, each with the PUF challenges of the members of its shard.
+type Signed = Vec<(Arc<SignedGroupKey>, Vec<String>)>;
+
+/// Generate a fresh `k_d` and sign one distribution message per shard.
+///
+/// `shards` holds the members of each shard with an `eta` congruent to 1 modulo each of their primes.
+fn sign(cfg: &GSConfig, group_id: &str, epoch: u64, shards: Vec<(Vec<String>, Integer)>) -> Option<Signed> {
+    let bytes = rand::random::<[u8; 16]>();
+    let kd = Integer::from_digits(&bytes, Order::MsfBe);
+    info!("UAV group key: {}", abbreviate_key_default(&kd.to_string_radix(16)));
+
+    let kcv = hex::encode(key_check_value(group_id, epoch, &kd));
+    let t_g = chrono::Utc::now().timestamp();
+    let count = shards.len() as u32;
+
+    shards
+        .into_par_iter()
+        .enumerate()
+        .map(|(j, (members, eta))| {
+            let c = members
+                .iter()
+                .map(|uid| UAV_LIST.0.get(uid).map(|uav| uav.challenge()))
+                .collect::<Option<Vec<_>>>()?;
+            let mu = Integer::from(&kd * &eta);
+
+            let msg = GroupKeyMessage {
+                gid: cfg.gid.clone(),
+                group_id: group_id.to_string(),
+                epoch,
+                shard: j as u32,
+                shards: count,
+                members,
+                mu: mu.to_string_radix(16),
+                kcv: kcv.clone(),
+                t_g,
+            };
+            let h = G1Projective::hash::<ExpandMsgXmd<blake2::Blake2b512>>(&msg.signing_bytes(), GROUP_KEY_DST);
+            let sigma = (h * cfg.sk).to_compressed().encode_hex::<String>();
+            Some((Arc::new(SignedGroupKey { msg, sigma }), c))
+        })
+        .collect()
+}
+
+/// Queue every signed message for the members of its shard except `skip`.
+fn deliver_all(signed: &Signed, skip: Option<&str>) {
+    for (key, c) in signed {
+        for (uid, c_i) in key.msg.members.iter().zip(c) {
+            if Some(uid.as_str()) != skip {
+                deliver(uid, key, c_i.clone());
+            }
+        }
+    }
+}
+
+fn keys(signed: Signed) -> Vec<Arc<SignedGroupKey>> {
+    signed.into_iter().map(|(key, _)| key).collect()
+}
+
+/// Generate a fresh `k_d`, sign one distribution message per shard and queue it for every member except `skip`.
+///
+/// `shards` holds the members of each shard with an `eta` congruent to 1 modulo each of their primes.
+pub(crate) fn distribute(
+    cfg: &GSConfig,
+    group_id: &str,
+    epoch: u64,
+    shards: Vec<(Vec<String>, Integer)>,
+    skip: Option<&str>,
+) -> Option<Vec<Arc<SignedGroupKey>>> {
+    let signed = sign(cfg, group_id, epoch, shards)?;
+    deliver_all(&signed, skip);
+    Some(keys(signed))
+}
+
+/// Move `group` to the next epoch and sign a fresh key for its members.
+///
+/// Works on a copy taken out of [`GROUPS`], so the CRT update and signing run without holding
+/// its guard; [`commit`] installs the copy afterwards.
+fn rekey(cfg: &GSConfig, name: &str, group: &mut Group) -> Option<Signed> {
+    let shards = group
+        .shards
+        .iter()
+        .map(|shard| (shard.members.clone(), shard.crt.eta().clone()))
+        .collect();
+    let signed = sign(cfg, name, group.epoch + 1, shards)?;
+    group.epoch += 1;
+    Some(signed)
+}
+
+fn log_rekey(name: &str, group: &Group) {
+    info!(
+        "Group {} rekeyed to epoch {} with {} members in {} shards",
+        name,
+        group.epoch,
+        group.members().count(),
+        group.shards.len()
+    );
+}
+
+/// Replace group `name` by `updated` and queue its keys, unless the group moved past `base_epoch`
+/// or was dissolved since the copy was taken.
+fn commit(name: &str, base_epoch: u64, updated: Group, signed: &Signed, skip: Option<&str>) -> bool {
+    let Some(mut group) = GROUPS.get_mut(name) else {
+        warn!("Group {} was dissolved during the update", name);
+        return false;
+    };
+    if group.epoch != base_epoch {
+        warn!("Group {} changed during the update, epoch {} dropped", name, updated.epoch);
+        return false;
+    }
+    // queued under the guard so mailboxes see the epochs of a group in order
+    deliver_all(signed, skip);
+    *group = updated;
+    log_rekey(name, &group);
+    true
+}
+
+/// Remove a group from [`GROUPS`], remember its last epoch and drop its undelivered messages.
+fn retire(entry: OccupiedEntry<'_, String, Group>) {
+    RETIRED_EPOCHS.insert(entry.key().clone(), entry.get().epoch);
+    let (name, group) = entry.remove_entry();
+    for uid in group.members() {
+        if let Some(mut mailbox) = GROUP_MAILBOX.get_mut(uid) {
+            mailbox.retain(|d| d.key.msg.group_id != name);
+        }
+    }
+}
+
+/// Epoch a group created under `name` starts above.
+fn retired_epoch(name: &str) -> u64 {
+    RETIRED_EPOCHS.get(name).map_or(0, |epoch| *epoch)
+}
+
+/// Create group `name` owned by `owner`; the owner is always a member.
+///
+/// A name used before continues at the epoch its last group reached, since members reject
+/// keys of any epoch at or below one they already accepted.
+pub(crate) fn create(
+    cfg: &GSConfig,
+    policy: &dyn GroupPolicy,
+    name: &str,
+    owner: &str,
+    mut members: Vec<String>,
+    shard_size: usize,
+) -> Option<UavCommResponse> {
+    if !members.iter().any(|m| m == owner) {
+        members.insert(0, owner.to_string());
+    }
+    let mut seen = HashSet::new();
+    members.retain(|m| seen.insert(m.clone()));
+    if !policy.allow(owner, &members) {
+        warn!("Group {} denied by policy for uid: {}", name, abbreviate_key_default(owner));
+        return None;
+    }
+    if GROUPS.contains_key(name) {
+        warn!("Group {} already exists", name);
+        return None;
+    }
+
+    let base_epoch = retired_epoch(name);
+    let shard_size = shard_size.max(1);
+    let shards = members
+        .chunks(shard_size)
+        .map(|chunk| {
+            Some(Shard {
+                members: chunk.to_vec(),
+                crt: CrtGroup::new(member_primes(chunk)?).ok()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let mut group = Group {
+        owner: owner.to_string(),
+        shards,
+        shard_size,
+        epoch: base_epoch,
+    };
+    let signed = rekey(cfg, name, &mut group)?;
+
+    let Entry::Vacant(entry) = GROUPS.entry(name.to_string()) else {
+        warn!("Group {} already exists", name);
+        return None;
+    };
+    if retired_epoch(name) != base_epoch {
+        warn!("Group {} was re-created during the update", name);
+        return None;
+    }
+    deliver_all(&signed, Some(owner));
+    log_rekey(name, &group);
+    entry.insert(group);
+    response_for(&keys(signed), owner)
+}
+
+/// Add `uid` to the first shard of group `name` with room left and rekey.
+pub(crate) fn join(cfg: &GSConfig, policy: &dyn GroupPolicy, name: &str, uid: &str) -> Option<UavCommResponse> {
+    let mut group = GROUPS.get(name)?.clone();
+    let base_epoch = group.epoch;
+    if group.contains(uid) {
+        warn!("UAV {} is already a member of group {}", abbreviate_key_default(uid), name);
+        return None;
+    }
+    let mut members = group.members().cloned().collect::<Vec<_>>();
+    members.push(uid.to_string());
+    if !policy.allow_join(uid, &members) {
+        warn!("Join of group {} denied by policy for uid: {}", name, abbreviate_key_default(uid));
+        return None;
+    }
+
+    let p = member_prime(uid)?;
+    let shard_size = group.shard_size;
+    let j = match group.shards.iter().position(|shard| shard.members.len() < shard_size) {
+        Some(j) => j,
+        None => {
+            group.shards.push(Shard {
+                members: vec![],
+                crt: CrtGroup::default(),
+            });
+            group.shards.len() - 1
+        }
+    };
+    if let Err(e) = group.shards[j].crt.add(p) {
+        warn!("UAV {} cannot join group {}: {}", abbreviate_key_default(uid), name, e);
+        return None;
+    }
+    group.shards[j].members.push(uid.to_string());
+
+    let signed = rekey(cfg, name, &mut group)?;
+    if !commit(name, base_epoch, group, &signed, Some(uid)) {
+        return None;
+    }
+    response_for(&keys(signed), uid)
+}
+
+/// Remove `target` from group `name` on behalf of `requester` and rekey the remaining members.
+///
+/// A member may always remove itself; only the owner may remove others. A group left without
+/// members is dissolved, and ownership passes to the longest-standing member when the owner leaves.
+/// Nothing changes when the rekey fails.
+pub(crate) fn remove(cfg: &GSConfig, name: &str, requester: &str, target: &str) -> bool {
+    let Some(mut group) = GROUPS.get(name).map(|group| group.clone()) else {
+        return false;
+    };
+    let base_epoch = group.epoch;
+    if requester != target && group.owner != requester {
+        warn!("UAV {} may not evict members of group {}", abbreviate_key_default(requester), name);
+        return false;
+    }
+    let Some((j, idx)) = group
+        .shards
+        .iter()
+        .enumerate()
+        .find_map(|(j, shard)| Some((j, shard.members.iter().position(|m| m == target)?)))
+    else {
+        return false;
+    };
+    let Some(p) = member_prime(target) else {
+        return false;
+    };
+
+    let shard = &mut group.shards[j];
+    shard.members.remove(idx);
+    shard.crt.remove(&p);
+    if shard.members.is_empty() {
+        group.shards.remove(j);
+    }
+    if group.shards.is_empty() {
+        let Entry::Occupied(entry) = GROUPS.entry(name.to_string()) else {
+            return false;
+        };
+        if entry.get().epoch != base_epoch {
+            warn!("Group {} changed during the update", name);
+            return false;
+        }
+        retire(entry);
+        info!("Group {} dissolved after its last member left", name);
+        return true;
+    }
+    if group.owner == target {
+        group.owner = group.shards[0].members[0].clone();
+    }
+    let Some(signed) = rekey(cfg, name, &mut group) else {
+        return false;
+    };
+    commit(name, base_epoch, group, &signed, None)
+}
+
+/// Dissolve group `name`; only its owner may do so.
+pub(crate) fn dissolve(name: &str, requester: &str) -> bool {
+    let Entry::Occupied(entry) = GROUPS.entry(name.to_string()) else {
+        return false;
+    };
+    if entry.get().owner != requester {
+        return false;
+    }
+    retire(entry);
+    info!("Group {} dissolved by its owner", name);
+    true
+}
+
+/// Current epoch of group `name`, visible to its members only.
+pub(crate) fn epoch(name: &str, uid: &str) -> Option<u64> {
+    let group = GROUPS.get(name)?;
+    group.contains(uid).then_some(group.epoch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        policy::{FleetPolicy, OpenPolicy},
+        PufChallenge, UavInfo, PRIME_SIZE,
+    };
+    use blstrs_plus::ff::Field;
+    use blstrs_plus::{group::prime::PrimeCurveAffine, G1Affine, G2Affine, Scalar};
+
+    fn config() -> GSConfig {
+        let sk = Scalar::random(rand::thread_rng());
+        GSConfig {
+            gid: "gs".to_string(),
+            sk,
+            pk_g1: (G1Affine::generator() * sk).into(),
+            pk: (G2Affine::generator() * sk).into(),
+        }
+    }
+
+    /// Register UAVs named `{prefix}{i}` with small distinct primes.
+    fn register(prefix: &str, primes: &[u8]) -> Vec<String> {
+        primes
+            .iter()
+            .enumerate()
+            .map(|(i, &p_i)| {
+                let uid = format!("{prefix}{i}");
+                let mut p = [0u8; PRIME_SIZE];
+                p[PRIME_SIZE - 1] = p_i;
+                let info = UavInfo {
+                    pk: G2Affine::generator(),
+                    z: G1Affine::generator(),
+                    c: PufChallenge::from_slice(&[0xAB; 12]),
+                    p,
+                };
+                UAV_LIST.0.insert(uid.clone(), info);
+                uid
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_recreated_group_continues_epochs() {
+        let cfg = config();
+        let uids = register("recreate-", &[101, 103]);
+        let first = create(&cfg, &OpenPolicy, "recreate", &uids[0], uids.clone(), 8).unwrap();
+        assert_eq!(first.msg.epoch, 1);
+        assert!(take(&uids[1]).iter().any(|r| r.msg.group_id == "recreate"));
+        assert!(remove(&cfg, "recreate", &uids[0], &uids[1]));
+        assert!(dissolve("recreate", &uids[0]));
+
+        let again = create(&cfg, &OpenPolicy, "recreate", &uids[0], uids.clone(), 8).unwrap();
+        assert_eq!(again.msg.epoch, 3);
+        assert!(dissolve("recreate", &uids[0]));
+        // undelivered keys of a dissolved group are dropped
+        assert!(take(&uids[1]).is_empty());
+    }
+
+    #[test]
+    fn test_failed_remove_leaves_group_unchanged() {
+        let cfg = config();
+        let uids = register("rollback-", &[107, 109, 113]);
+        create(&cfg, &OpenPolicy, "rollback", &uids[0], uids.clone(), 2).unwrap();
+
+        // signing needs the challenge of every remaining member
+        let (_, info) = UAV_LIST.0.remove(&uids[2]).unwrap();
+        assert!(!remove(&cfg, "rollback", &uids[0], &uids[1]));
+        assert_eq!(epoch("rollback", &uids[1]), Some(1));
+        assert_eq!(GROUPS.get("rollback").unwrap().members().count(), 3);
+
+        UAV_LIST.0.insert(uids[2].clone(), info);
+        assert!(remove(&cfg, "rollback", &uids[0], &uids[1]));
+        assert_eq!(epoch("rollback", &uids[0]), Some(2));
+        assert_eq!(epoch("rollback", &uids[1]), None);
+    }
+
+    #[test]
+    fn test_join_takes_a_fleet_policy() {
+        let cfg = config();
+        let uids = register("join-", &[127, 131]);
+        create(&cfg, &OpenPolicy, "join", &uids[0], vec![], 8).unwrap();
+        assert!(join(&cfg, &OpenPolicy, "join", &uids[1]).is_none());
+        assert!(!GROUPS.get("join").unwrap().contains(&uids[1]));
+
+        let fleets = uids.iter().map(|uid| (uid.clone(), "fleet".to_string())).collect();
+        let joined = join(&cfg, &FleetPolicy::new(fleets), "join", &uids[1]).unwrap();
+        assert_eq!(joined.msg.epoch, 2);
+    }
+}
@@ -1,17 +1,36 @@
 mod auth;
+mod batch;
+mod coalesce;
+mod group;
 mod mem;
+mod policy;
+mod pool;
+mod prepared;
 mod reg;
 mod rpc_impl;
-use crate::rpc_impl::GS;
+mod secure;
+use crate::{
+    policy::{FleetPolicy, GroupPolicy, OpenPolicy},
+    pool::VerifyPool,
+    prepared::PreparedCache,
+    rpc_impl::GS,
+};
 use auth::auth;
-use blstrs_plus::{group::prime::PrimeCurveAffine, G1Affine, G2Affine, Scalar};
+use blstrs_plus::{ff::Field, group::prime::PrimeCurveAffine, G1Affine, G2Affine, Scalar};
 use dashmap::DashMap;
 use futures::{future, lock::Mutex, StreamExt};
 use rand::{thread_rng, Rng};
 use reg::register;
-use rpc::{GsRpc, TaRpcClient};
-use rug::Integer;
-use std::future::Future;
+use rpc::{GsAuthResponseStruct, GsCertificate, GsRpc, TaRpcClient, TA_MAX_FRAME_LENGTH};
+use rug::{integer::Order, Integer};
+use smallvec::SmallVec;
+use std::{
+    future::Future,
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 use tarpc::{
     client, context,
     server::{self, Channel},
@@ -19,7 +38,7 @@ use tarpc::{
 };
 use tracing::info;
 use tracing_subscriber::EnvFilter;
-use utils::abbreviate_key_default;
+use utils::{abbreviate_key_default, CrtBackend};
 
 #[global_allocator]
 static GLOBAL: mem::TrackingAllocator = mem::TrackingAllocator;
@@ -32,13 +51,55 @@ pub struct GSConfig {
     pub pk: G2Affine,
 }
 
+/// Bytes of the widest PUF challenge the TA issues.
+pub const PUF_CHALLENGE_SIZE: usize = rpc::MAX_PUF_BITS / 8;
+/// Challenges of up to this many bytes are kept inline: the 96-bit ones of `puf_py` and the
+/// `puf_uart` bitstream, and 128-bit ones.
+pub const PUF_CHALLENGE_INLINE: usize = 16;
+/// Raw bytes of a PUF challenge; one wider than [`PUF_CHALLENGE_INLINE`] moves to the heap.
+pub type PufChallenge = SmallVec<[u8; PUF_CHALLENGE_INLINE]>;
+/// Bytes of a PUF prime `p_i`, packed big-endian.
+pub const PRIME_SIZE: usize = 32;
+
+/// Registry record of a UAV, decoded once when the TA hands over the UAV list.
+///
+/// The raw challenge bytes, inline unless wider than 128 bits, `z` as an affine point and `p`
+/// packed into 32 bytes, instead of hex strings and a heap-allocated `Integer`. The uid is the
+/// map key.
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 pub struct UavInfo {
-    pub uid: String,
     pub pk: G2Affine,
-    pub c: String,
-    pub z: String,
-    pub p: Integer,
+    pub z: G1Affine,
+    pub c: PufChallenge,
+    pub p: [u8; PRIME_SIZE],
+}
+
+impl UavInfo {
+    /// Decode a record of the UAV list sent by the TA.
+    pub fn decode(uav: &GsAuthResponseStruct) -> anyhow::Result<Self> {
+        let pk = Option::from(G2Affine::from_compressed_hex(&uav.pk_u)).ok_or_else(|| anyhow::anyhow!("invalid public key"))?;
+        let z = Option::from(G1Affine::from_compressed_hex(&uav.z)).ok_or_else(|| anyhow::anyhow!("invalid z value"))?;
+        let c = PufChallenge::from_vec(hex::decode(&uav.c)?);
+        if c.is_empty() || c.len() > PUF_CHALLENGE_SIZE {
+            anyhow::bail!("challenge of {} bytes exceeds {PUF_CHALLENGE_SIZE} bytes", c.len());
+        }
+        let digits = uav.p.to_digits::<u8>(Order::MsfBe);
+        if digits.len() > PRIME_SIZE {
+            anyhow::bail!("prime exceeds {PRIME_SIZE} bytes");
+        }
+        let mut p = [0u8; PRIME_SIZE];
+        p[PRIME_SIZE - digits.len()..].copy_from_slice(&digits);
+        Ok(Self { pk, z, c, p })
+    }
+
+    /// The PUF challenge in the hex form used on the wire and in the protocol hashes.
+    pub fn challenge(&self) -> String {
+        hex::encode(&self.c)
+    }
+
+    pub fn prime(&self) -> Integer {
+        Integer::from_digits(&self.p, Order::MsfBe)
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -46,21 +107,32 @@ pub struct UavList(DashMap<String, UavInfo>);
 
 const TAG: &[u8] = b"BLS_SIG_BLS12381G1_XMD:BLAKE2b-512_SSWU_RO_NUL_";
 const T_MAX: i64 = 10;
+/// Default window in milliseconds for coalescing single phase 2 requests: off, as the window delays
+/// every single authentication. Operators expecting bursts enable it with `GS_AUTH_COALESCE_MS`.
+const DEFAULT_AUTH_COALESCE_MS: u64 = 0;
+/// Default number of client connections served at once.
+const DEFAULT_MAX_CONNECTIONS: usize = 1024;
 
 lazy_static::lazy_static! {
     pub static ref GS_CONFIG: GSConfig = init_gs_keys();
     pub static ref UAV_LIST: UavList = UavList(DashMap::new());
-    pub static ref UAV_SESSION_KEYS: DashMap<String, String> = DashMap::new();
+    /// `pk_u` of recently authenticated UAVs with its Miller loop lines precomputed, about 19 KiB each.
+    pub static ref UAV_PREPARED: PreparedCache = PreparedCache::new(prepared::DEFAULT_PREPARED_CAPACITY);
+    pub static ref UAV_SESSION_KEYS: DashMap<String, secure::SessionKey> = DashMap::new();
     pub static ref UAV_FAKE_PRIME: Mutex<Vec<Integer>> = Mutex::new(vec![]);
 }
 
 /// Init GS keys
+///
+/// The identity comes from `GS_ID`, which the TA's allowlist must name, and the secret key from the
+/// file at `GS_KEY_FILE`, created on first start, so a restarted GS registers again under the key
+/// the TA already holds. Both are random when unset.
 fn init_gs_keys() -> GSConfig {
-    let gid: [u8; 32] = thread_rng().gen::<[u8; 32]>();
-    let sk_bytes = rand::thread_rng().gen::<[u64; 4]>();
-
-    let gid = hex::encode(gid);
-    let sk = Scalar::from_raw_unchecked(sk_bytes);
+    let gid = std::env::var("GS_ID").unwrap_or_else(|_| hex::encode(thread_rng().gen::<[u8; 32]>()));
+    let sk = match std::env::var("GS_KEY_FILE") {
+        Ok(path) => load_or_create_key(&path).expect("Cannot load GS key file"),
+        Err(_) => Scalar::from_raw_unchecked(rand::thread_rng().gen::<[u64; 4]>()),
+    };
     let pk_g1 = G1Affine::generator() * sk;
     let g = G2Affine::generator();
     let pk = g * sk;
@@ -73,6 +145,26 @@ fn init_gs_keys() -> GSConfig {
     }
 }
 
+/// Secret key stored as big-endian hex at `path`, generated and written owner-only if missing.
+fn load_or_create_key(path: &str) -> anyhow::Result<Scalar> {
+    match std::fs::read_to_string(path) {
+        Ok(hex) => {
+            let bytes: [u8; 32] = hex::decode(hex.trim())?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("GS key must be 32 bytes"))?;
+            Option::from(Scalar::from_be_bytes(&bytes)).ok_or_else(|| anyhow::anyhow!("GS key is not a valid scalar"))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let sk = Scalar::random(thread_rng());
+            let mut f = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+            f.write_all(hex::encode(sk.to_be_bytes()).as_bytes())?;
+            info!("Created GS key file {}", path);
+            Ok(sk)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // tracing logger
@@ -85,10 +177,7 @@ async fn main() -> anyhow::Result<()> {
     let ta_addr = "0.0.0.0:8090";
     let bind_addr = "0.0.0.0:8091";
 
-    let mut transport = tarpc::serde_transport::tcp::connect(&ta_addr, Json::default);
-    transport.config_mut().max_frame_length(usize::MAX);
-
-    let client = TaRpcClient::new(client::Config::default(), transport.await?).spawn();
+    let client = connect_ta(ta_addr).await?;
 
     let pk_t1 = client.get_ta_pubkey1(context::current()).await?;
     let pk_t1 = G1Affine::from_compressed_hex(&pk_t1).expect("Invalid trust authority G1 public key");
@@ -98,9 +187,16 @@ async fn main() -> anyhow::Result<()> {
     let pk_t2 = G2Affine::from_compressed_hex(&pk_t2).expect("Invalid trust authority G2 public key");
     info!("TA's G2 public key: {}", abbreviate_key_default(&hex::encode(pk_t2.to_compressed())));
 
+    let prepared_capacity = match std::env::var("GS_PREPARED_CACHE_SIZE") {
+        Ok(size) => size.parse::<usize>()?,
+        Err(_) => prepared::DEFAULT_PREPARED_CAPACITY,
+    };
+    UAV_PREPARED.set_capacity(prepared_capacity);
+    info!("Prepared UAV key cache: {} entries", prepared_capacity);
+
     // register self to TA
     let register_start = mem::reset_phase_peak();
-    register(&client).await?;
+    let cert = Arc::new(RwLock::new(register(&client).await?));
     mem::log_phase("register_gs", register_start);
 
     // auth self to TA
@@ -109,8 +205,9 @@ async fn main() -> anyhow::Result<()> {
     mem::log_phase("auth_gs", auth_start);
     mem::log_uav_storage_stats("uav_list_loaded");
 
-    // spawn the server
-    tokio::spawn(server(bind_addr, pk_t2));
+    // spawn the server and keep its certificate current
+    tokio::spawn(reg::renew(ta_addr, cert.clone()));
+    tokio::spawn(server(bind_addr, pk_t2, cert));
 
     // wait for exit
     tokio::signal::ctrl_c().await?;
@@ -118,9 +215,15 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn server(bind_addr: &str, pk_t: G2Affine) -> anyhow::Result<()> {
+/// Open an RPC connection to the TA at `ta_addr`.
+pub(crate) async fn connect_ta(ta_addr: &str) -> anyhow::Result<TaRpcClient> {
+    let mut transport = tarpc::serde_transport::tcp::connect(&ta_addr, Json::default);
+    transport.config_mut().max_frame_length(TA_MAX_FRAME_LENGTH);
+    Ok(TaRpcClient::new(client::Config::default(), transport.await?).spawn())
+}
+
+async fn server(bind_addr: &str, pk_t: G2Affine, cert: Arc<RwLock<GsCertificate>>) -> anyhow::Result<()> {
     let mut listener = tarpc::serde_transport::tcp::listen(&bind_addr, Json::default).await?;
-    listener.config_mut().max_frame_length(usize::MAX);
     tracing::info!("Listening on port {}", listener.local_addr().port());
     mem::log_checkpoint("server_ready");
 
@@ -131,14 +234,83 @@ async fn server(bind_addr: &str, pk_t: G2Affine) -> anyhow::Result<()> {
         pk: GS_CONFIG.pk,
     };
 
-    let server = GS::new(cfg, pk_t);
+    let policy: Arc<dyn GroupPolicy> = match std::env::var("GS_GROUP_POLICY") {
+        Ok(path) => {
+            info!("Group formation policy: fleets from {}", path);
+            Arc::new(FleetPolicy::from_file(&path)?)
+        }
+        Err(_) => Arc::new(OpenPolicy),
+    };
+
+    let crt_backend = match std::env::var("GS_CRT_BACKEND") {
+        Ok(name) => name.parse::<CrtBackend>()?,
+        Err(_) => CrtBackend::default(),
+    };
+    info!("CRT backend for one-shot groups: {:?}", crt_backend);
+
+    let shard_size = match std::env::var("GS_CRT_SHARD_SIZE") {
+        Ok(size) => size.parse::<usize>()?,
+        Err(_) => rpc::DEFAULT_SHARD_SIZE,
+    };
+    if shard_size == 0 {
+        anyhow::bail!("GS_CRT_SHARD_SIZE must be positive");
+    }
+    info!("CRT shard size: {} members", shard_size);
+    listener.config_mut().max_frame_length(rpc::max_frame_length(shard_size));
+
+    let session_ttl = match std::env::var("GS_SESSION_TTL") {
+        Ok(secs) => secs.parse::<i64>()?,
+        Err(_) => secure::DEFAULT_SESSION_TTL,
+    };
+    if session_ttl <= 0 {
+        anyhow::bail!("GS_SESSION_TTL must be positive");
+    }
+    secure::set_session_ttl(session_ttl);
+    info!("Session key lifetime: {} s", session_ttl);
+
+    let coalesce_ms = match std::env::var("GS_AUTH_COALESCE_MS") {
+        Ok(ms) => ms.parse::<u64>()?,
+        Err(_) => DEFAULT_AUTH_COALESCE_MS,
+    };
+
+    let verify_threads = match std::env::var("GS_VERIFY_THREADS") {
+        Ok(threads) => threads.parse::<usize>()?,
+        Err(_) => 0,
+    };
+    let verify_queue = match std::env::var("GS_VERIFY_QUEUE") {
+        Ok(depth) => depth.parse::<usize>()?,
+        Err(_) => pool::DEFAULT_QUEUE_DEPTH,
+    };
+    if verify_queue == 0 {
+        anyhow::bail!("GS_VERIFY_QUEUE must be positive");
+    }
+    let verify_pool = VerifyPool::new(verify_threads, verify_queue)?;
+    info!("Verification pool: {} workers, queue depth {}", verify_pool.threads(), verify_queue);
+
+    let max_connections = match std::env::var("GS_MAX_CONNECTIONS") {
+        Ok(max) => max.parse::<usize>()?,
+        Err(_) => DEFAULT_MAX_CONNECTIONS,
+    };
+    if max_connections == 0 {
+        anyhow::bail!("GS_MAX_CONNECTIONS must be positive");
+    }
+
+    let mut server = GS::new(cfg, pk_t, cert, verify_pool)
+        .with_policy(policy)
+        .with_crt_backend(crt_backend)
+        .with_shard_size(shard_size);
+    if coalesce_ms > 0 {
+        info!("Coalescing single phase 2 requests over {} ms", coalesce_ms);
+        server = server.with_auth_coalescing(Duration::from_millis(coalesce_ms));
+    }
 
     listener
         // Ignore accept errors.
         .filter_map(|r| future::ready(r.ok()))
         .map(server::BaseChannel::with_defaults)
         .map(|channel| channel.execute(server.clone().serve()).for_each(spawn))
-        .buffer_unordered(usize::MAX)
+        // connections beyond the limit wait in the accept backlog
+        .buffer_unordered(max_connections)
         .for_each(|_| async {})
         .await;
     Ok(())
@@ -147,3 +319,28 @@ async fn server(bind_addr: &str, pk_t: G2Affine) -> anyhow::Result<()> {
 async fn spawn(fut: impl Future<Output = ()> + Send + 'static) {
     tokio::spawn(fut);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::ToHex;
+
+    #[test]
+    fn test_challenge_stays_inline_up_to_128_bits() {
+        let record = |c: &[u8]| GsAuthResponseStruct {
+            uid: "uav".to_string(),
+            pk_u: G2Affine::generator().to_compressed().encode_hex(),
+            c: hex::encode(c),
+            z: G1Affine::generator().to_compressed().encode_hex(),
+            p: Integer::from(65537),
+        };
+        for (len, spilled) in [(12, false), (16, false), (32, true)] {
+            let info = UavInfo::decode(&record(&vec![0xAB; len])).unwrap();
+            assert_eq!(info.c.spilled(), spilled);
+            assert_eq!(info.challenge(), "ab".repeat(len));
+            assert_eq!(info.prime(), 65537);
+        }
+        assert!(UavInfo::decode(&record(&[])).is_err());
+        assert!(UavInfo::decode(&record(&[0xAB; PUF_CHALLENGE_SIZE + 1])).is_err());
+    }
+}
@@ -2,10 +2,11 @@ use std::alloc::{GlobalAlloc, Layout, System};
 use std::mem::size_of;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use blstrs_plus::G2Affine;
 use rug::Integer;
 use tracing::info;
 
-use crate::{UAV_LIST, UavInfo};
+use crate::{UAV_LIST, UAV_PREPARED, UavInfo};
 
 pub struct TrackingAllocator;
 
@@ -17,8 +18,16 @@ pub struct UavStorageStats {
     pub count: usize,
     pub total_bytes: usize,
     pub avg_bytes: usize,
+    /// What the same records took with hex strings and heap primes.
+    pub legacy_bytes: usize,
+    /// Keys held by the bounded cache of prepared `pk_u`, on top of `total_bytes`.
+    pub prepared_count: usize,
+    pub prepared_bytes: usize,
 }
 
+/// Registry sizes the storage stats are projected to.
+const PROJECTED_UAV_COUNTS: [usize; 2] = [10_000, 100_000];
+
 unsafe impl GlobalAlloc for TrackingAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let ptr = unsafe { System.alloc(layout) };
@@ -127,11 +136,17 @@ pub fn log_checkpoint(label: &str) {
 pub fn collect_uav_storage_stats() -> UavStorageStats {
     let mut count = 0usize;
     let mut total_bytes = 0usize;
+    let mut legacy_bytes = 0usize;
 
     for entry in UAV_LIST.0.iter() {
         count += 1;
         total_bytes += estimate_string_total(entry.key());
-        total_bytes += estimate_uav_info_total(entry.value());
+        total_bytes += size_of::<UavInfo>();
+        if entry.value().c.spilled() {
+            total_bytes += entry.value().c.capacity();
+        }
+        legacy_bytes += estimate_string_total(entry.key());
+        legacy_bytes += estimate_legacy_uav_info_total(entry.key(), entry.value());
     }
 
     let avg_bytes = if count == 0 { 0 } else { total_bytes / count };
@@ -139,6 +154,9 @@ pub fn collect_uav_storage_stats() -> UavStorageStats {
         count,
         total_bytes,
         avg_bytes,
+        legacy_bytes,
+        prepared_count: UAV_PREPARED.len(),
+        prepared_bytes: UAV_PREPARED.bytes(),
     }
 }
 
@@ -152,14 +170,44 @@ pub fn log_uav_storage_stats(label: &str) {
         stats.avg_bytes,
         bytes_to_kib(stats.avg_bytes),
     );
+    info!(
+        "storage[{label}] prepared_cache={}/{} keys, {}B ({:.2} KiB)",
+        stats.prepared_count,
+        UAV_PREPARED.capacity(),
+        stats.prepared_bytes,
+        bytes_to_kib(stats.prepared_bytes),
+    );
+    if stats.count == 0 {
+        return;
+    }
+    let legacy_avg = stats.legacy_bytes / stats.count;
+    info!(
+        "storage[{label}] legacy_total={}B ({:.2} KiB), avg_per_uav={}B, saving_per_uav={}B",
+        stats.legacy_bytes,
+        bytes_to_kib(stats.legacy_bytes),
+        legacy_avg,
+        legacy_avg.saturating_sub(stats.avg_bytes),
+    );
+    for n in PROJECTED_UAV_COUNTS {
+        info!(
+            "storage[{label}] projected for {} UAVs: compact={:.2} MiB, legacy={:.2} MiB, saving={:.2} MiB",
+            n,
+            bytes_to_mib(stats.avg_bytes * n),
+            bytes_to_mib(legacy_avg * n),
+            bytes_to_mib(legacy_avg.saturating_sub(stats.avg_bytes) * n),
+        );
+    }
 }
 
-fn estimate_uav_info_total(info: &UavInfo) -> usize {
-    size_of::<UavInfo>()
-        + info.uid.len()
-        + info.c.len()
-        + info.z.len()
-        + estimate_integer_heap_bytes(&info.p)
+/// Size of the former record: uid, hex challenge and hex compressed `z` as `String`s, `p` as an `Integer`.
+fn estimate_legacy_uav_info_total(uid: &str, info: &UavInfo) -> usize {
+    3 * size_of::<String>()
+        + size_of::<G2Affine>()
+        + size_of::<Integer>()
+        + uid.len()
+        + 2 * info.c.len()
+        + 2 * info.z.to_compressed().len()
+        + estimate_integer_heap_bytes(&info.prime())
 }
 
 fn estimate_string_total(s: &str) -> usize {
@@ -174,3 +222,7 @@ fn estimate_integer_heap_bytes(n: &Integer) -> usize {
 fn bytes_to_kib(bytes: usize) -> f64 {
     bytes as f64 / 1024.0
 }
+
+fn bytes_to_mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
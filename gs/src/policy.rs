@@ -0,0 +1,60 @@
+use std::{collections::HashMap, fmt::Debug, path::Path};
+
+/// Decides whether `requester` may form a group with `members`.
+///
+/// The GS has already checked that the requester holds an active session and is
+/// itself part of `members` before the policy is consulted.
+pub trait GroupPolicy: Debug + Send + Sync {
+    fn allow(&self, requester: &str, members: &[String]) -> bool;
+
+    /// Decides whether `uid` may join an existing group, `members` already including it.
+    fn allow_join(&self, uid: &str, members: &[String]) -> bool {
+        self.allow(uid, members)
+    }
+}
+
+/// Any authenticated UAV may group with any registered UAV.
+///
+/// Nobody may join an existing group on their own though, since no owner agreed to them; that
+/// takes a policy which assigns the UAVs to fleets.
+#[derive(Debug, Default)]
+pub struct OpenPolicy;
+
+impl GroupPolicy for OpenPolicy {
+    fn allow(&self, _requester: &str, _members: &[String]) -> bool {
+        true
+    }
+
+    fn allow_join(&self, _uid: &str, _members: &[String]) -> bool {
+        false
+    }
+}
+
+/// UAVs may only group with UAVs assigned to the same fleet.
+///
+/// UAVs without a fleet assignment are never allowed into a group.
+#[derive(Debug, Default)]
+pub struct FleetPolicy {
+    fleets: HashMap<String, String>,
+}
+
+impl FleetPolicy {
+    pub fn new(fleets: HashMap<String, String>) -> Self {
+        Self { fleets }
+    }
+
+    /// Load a `{ "<uid>": "<fleet>" }` JSON map.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let f = std::fs::File::open(path)?;
+        Ok(Self::new(serde_json::from_reader(f)?))
+    }
+}
+
+impl GroupPolicy for FleetPolicy {
+    fn allow(&self, requester: &str, members: &[String]) -> bool {
+        let Some(fleet) = self.fleets.get(requester) else {
+            return false;
+        };
+        members.iter().all(|uid| self.fleets.get(uid) == Some(fleet))
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use tokio::sync::{oneshot, Semaphore};
+
+/// Default number of verification jobs queued or running at once.
+pub(crate) const DEFAULT_QUEUE_DEPTH: usize = 64;
+
+/// Dedicated worker pool for pairing-heavy verification, kept off the tokio runtime.
+///
+/// At most `queue_depth` jobs are queued or running at once; further jobs are refused rather
+/// than queued, so the GS can answer "busy" instead of letting latency grow without bound.
+/// Rayon parallelism inside a job runs on the same workers.
+#[derive(Debug, Clone)]
+pub(crate) struct VerifyPool {
+    pool: Arc<rayon::ThreadPool>,
+    slots: Arc<Semaphore>,
+}
+
+impl VerifyPool {
+    /// `threads == 0` uses one worker per CPU.
+    pub(crate) fn new(threads: usize, queue_depth: usize) -> anyhow::Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("gs-verify-{i}"))
+            .build()?;
+        Ok(Self {
+            pool: Arc::new(pool),
+            slots: Arc::new(Semaphore::new(queue_depth)),
+        })
+    }
+
+    pub(crate) fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Run `job` on the pool; `None` if the queue is full and the job was not started.
+    pub(crate) async fn run<R: Send + 'static>(&self, job: impl FnOnce() -> R + Send + 'static) -> Option<R> {
+        let slot = self.slots.clone().try_acquire_owned().ok()?;
+        let (tx, rx) = oneshot::channel();
+        self.pool.spawn(move || {
+            let _ = tx.send(job());
+            drop(slot);
+        });
+        rx.await.ok()
+    }
+}
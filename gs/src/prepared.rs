@@ -0,0 +1,127 @@
+use blstrs_plus::{G2Affine, G2Prepared};
+use dashmap::DashMap;
+use rand::Rng;
+use std::{
+    mem::size_of,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Default number of UAV keys kept prepared, about 76 MiB.
+pub(crate) const DEFAULT_PREPARED_CAPACITY: usize = 4096;
+/// Heap bytes of one `G2Prepared`: the 68 Miller loop lines of BLS12-381, an `Fp6` of 288 bytes each.
+const G2_PREPARED_HEAP_BYTES: usize = 68 * 288;
+
+/// `pk_u` of recently authenticated UAVs with its Miller loop lines precomputed.
+///
+/// Holds at most `capacity` keys. A full cache evicts an entry picked uniformly at random; an evicted
+/// key is prepared again on its next authentication.
+#[derive(Debug)]
+pub struct PreparedCache {
+    map: DashMap<String, Arc<G2Prepared>>,
+    capacity: AtomicUsize,
+}
+
+impl PreparedCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            map: DashMap::new(),
+            capacity: AtomicUsize::new(capacity),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Change the bound; takes effect on the next insertion.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Prepared `pk` of `uid`, computed and cached on a miss.
+    pub(crate) fn get_or_prepare(&self, uid: &str, pk: &G2Affine) -> Arc<G2Prepared> {
+        if let Some(prepared) = self.map.get(uid) {
+            return prepared.clone();
+        }
+        let prepared = Arc::new(G2Prepared::from(*pk));
+        self.insert(uid, prepared.clone());
+        prepared
+    }
+
+    /// Replace the cached key of `uid` after the UAV list changed, or cache it while there is
+    /// room, so a freshly loaded list starts warm without evicting anything.
+    pub(crate) fn refresh(&self, uid: &str, pk: &G2Affine) {
+        if self.map.contains_key(uid) || self.map.len() < self.capacity() {
+            self.map.insert(uid.to_string(), Arc::new(G2Prepared::from(*pk)));
+        }
+    }
+
+    fn insert(&self, uid: &str, prepared: Arc<G2Prepared>) {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return;
+        }
+        while self.map.len() >= capacity {
+            // the length may shrink concurrently, then the pick falls off the end and is retried
+            let i = rand::thread_rng().gen_range(0..self.map.len().max(1));
+            let Some(victim) = self.map.iter().nth(i).map(|entry| entry.key().clone()) else {
+                continue;
+            };
+            self.map.remove(&victim);
+        }
+        self.map.insert(uid.to_string(), prepared);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Bytes held by the cached keys, uids and `Arc` counters included.
+    pub(crate) fn bytes(&self) -> usize {
+        self.map
+            .iter()
+            .map(|entry| {
+                size_of::<String>() + entry.key().len() + 2 * size_of::<usize>() + size_of::<G2Prepared>() + G2_PREPARED_HEAP_BYTES
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs_plus::group::prime::PrimeCurveAffine;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_cache_stays_within_capacity() {
+        let cache = PreparedCache::new(3);
+        let pk = G2Affine::generator();
+        for i in 0..10 {
+            cache.get_or_prepare(&format!("uav{i}"), &pk);
+            assert!(cache.len() <= 3);
+        }
+        cache.refresh("late", &pk);
+        assert_eq!(cache.len(), 3);
+        cache.set_capacity(0);
+        cache.get_or_prepare("uncached", &pk);
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_eviction_picks_any_entry() {
+        let pk = G2Affine::generator();
+        let mut evicted = HashSet::new();
+        for _ in 0..64 {
+            let cache = PreparedCache::new(2);
+            cache.get_or_prepare("a", &pk);
+            cache.get_or_prepare("b", &pk);
+            cache.get_or_prepare("c", &pk);
+            evicted.extend(["a", "b"].into_iter().filter(|uid| !cache.map.contains_key(*uid)));
+        }
+        assert_eq!(evicted.len(), 2);
+    }
+}
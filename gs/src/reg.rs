@@ -1,23 +1,91 @@
+use blake2::Blake2b512;
+use blstrs_plus::{elliptic_curve::hash2curve::ExpandMsgXmd, G1Projective};
 use hex::ToHex;
-use rpc::TaRpcClient;
+use rpc::{GsCertificate, TaRpcClient};
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 use tarpc::context;
-use tracing::info;
+use tracing::{info, warn};
 use utils::abbreviate_key_default;
 
-use crate::GS_CONFIG;
+use crate::{connect_ta, GS_CONFIG, TAG};
 
-pub(crate) async fn register(client: &TaRpcClient) -> anyhow::Result<()> {
+/// Pause before retrying a renewal the TA did not answer or refused.
+const RENEW_RETRY: Duration = Duration::from_secs(60);
+
+/// Register with the TA and obtain the certificate of our G1 key for the scope the TA assigns us.
+pub(crate) async fn register(client: &TaRpcClient) -> anyhow::Result<GsCertificate> {
     let gid = GS_CONFIG.gid.clone();
     let pk1 = GS_CONFIG.pk_g1.to_compressed().encode_hex::<String>();
     let pk2 = GS_CONFIG.pk.to_compressed().encode_hex::<String>();
 
-    let req = rpc::GsRegisterRequest {
+    let mut req = rpc::GsRegisterRequest {
         gid: gid.clone(),
         gs_pubkey1: pk1,
         gs_pubkey2: pk2,
+        pop: String::new(),
+        endorsement: None,
     };
+    let h = G1Projective::hash::<ExpandMsgXmd<Blake2b512>>(&req.pop_bytes(), TAG);
+    req.pop = (h * GS_CONFIG.sk).to_compressed().encode_hex();
+
+    let cert = client
+        .register_gs(context::current(), req)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("TA refused to certify GS {gid}: not on its allowlist or registered under another key"))?;
+    info!(
+        "GS registered successfully: {}, certified for scope {} until {}",
+        abbreviate_key_default(&gid),
+        cert.scope,
+        cert.not_after
+    );
+    Ok(cert)
+}
+
+/// Time from `t_now` until `cert` is due for renewal.
+///
+/// That is once three quarters of its lifetime have passed, which leaves room for retries before UAVs reject it.
+pub(crate) fn renewal_delay(cert: &GsCertificate, t_now: i64) -> Duration {
+    let renew_at = cert.not_before + (cert.not_after - cert.not_before) * 3 / 4;
+    Duration::from_secs((renew_at - t_now).max(0) as u64)
+}
+
+/// Register with the TA at `ta_addr` again whenever the certificate in `cert` is due and swap in the renewed one.
+pub(crate) async fn renew(ta_addr: &str, cert: Arc<RwLock<GsCertificate>>) {
+    loop {
+        let delay = renewal_delay(&cert.read().unwrap(), chrono::Utc::now().timestamp());
+        tokio::time::sleep(delay).await;
+
+        // a fresh connection, the one from startup may not have outlived the TA
+        let renewed = async { register(&connect_ta(ta_addr).await?).await }.await;
+        match renewed {
+            Ok(renewed) => *cert.write().unwrap() = renewed,
+            Err(e) => {
+                warn!("Certificate renewal failed, retrying in {:?}: {}", RENEW_RETRY, e);
+                tokio::time::sleep(RENEW_RETRY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    client.register_gs(context::current(), req).await?;
-    info!("GS registered successfully: {}", abbreviate_key_default(&gid));
-    Ok(())
+    #[test]
+    fn test_renewal_is_due_before_expiry() {
+        let cert = GsCertificate {
+            gid: "gs".to_string(),
+            pk_g1: String::new(),
+            not_before: 1000,
+            not_after: 1000 + 24 * 60 * 60,
+            scope: rpc::ANY_SCOPE.to_string(),
+            sigma: String::new(),
+        };
+        assert_eq!(renewal_delay(&cert, 1000), Duration::from_secs(18 * 60 * 60));
+        assert_eq!(renewal_delay(&cert, 1000 + 18 * 60 * 60 + 1), Duration::ZERO);
+        assert_eq!(renewal_delay(&cert, cert.not_after + 1), Duration::ZERO);
+    }
 }
@@ -1,18 +1,23 @@
-use crate::{GSConfig, UavInfo, TAG, T_MAX, UAV_LIST, UAV_SESSION_KEYS};
-use ::pairing::MillerLoopResult as _;
-use blstrs_plus::{
-    elliptic_curve::hash2curve::ExpandMsgXmd,
-    group::{prime::PrimeCurveAffine, Group},
-    multi_miller_loop, pairing, G1Affine, G1Projective, G2Affine, G2Prepared, Scalar,
+use crate::{
+    batch::{self, BatchItem},
+    coalesce::AuthCoalescer,
+    group,
+    policy::{GroupPolicy, OpenPolicy},
+    pool::VerifyPool,
+    secure, GSConfig, TAG, T_MAX, UAV_LIST, UAV_PREPARED,
 };
+use blstrs_plus::{elliptic_curve::hash2curve::ExpandMsgXmd, G1Affine, G1Projective, G2Affine, G2Prepared, Scalar};
 use dashmap::DashMap;
 use hex::ToHex;
 use lazy_static::lazy_static;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use rpc::*;
-use rug::integer::Order;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 use tracing::info;
-use utils::{abbreviate_key_default, build_crt, derive_session_key_from_g1, hash_to_scalar};
+use utils::{abbreviate_key_default, derive_session_key_from_g1, g1_mul_generator, hash_to_scalar, CrtBackend};
 
 #[derive(Debug, Clone)]
 struct AuthSession {
@@ -24,15 +29,135 @@ lazy_static! {
     static ref AUTH_SESSIONS: DashMap<String, AuthSession> = DashMap::new();
 }
 
+/// Look up the phase 1 session of `req` and turn it into its term of the batch equation.
+fn prepare_phase2(req: &UavAuthRequest2, t_now: i64) -> Result<(BatchItem, AuthSession), BatchAuthStatus> {
+    let uid = &req.uid;
+    let (z, pk) = UAV_LIST.0.get(uid).map(|uav| (uav.z, uav.pk)).ok_or(BatchAuthStatus::UnknownUav)?;
+    let pk = UAV_PREPARED.get_or_prepare(uid, &pk);
+    let (_, session) = AUTH_SESSIONS.remove(uid).ok_or(BatchAuthStatus::NoSession)?;
+    if (t_now - req.t_u).abs() > T_MAX {
+        tracing::warn!("UAV authentication request too old: {}", t_now - req.t_u);
+        return Err(BatchAuthStatus::Stale);
+    }
+    let decode = |hex: &str| Option::<G1Affine>::from(G1Affine::from_compressed_hex(hex)).ok_or(BatchAuthStatus::Malformed);
+    let g_r = decode(&req.g_r)?;
+    let sigma = decode(&req.sigma)?;
+
+    let mut buf = Vec::with_capacity(session.challenge.len() + g_r.to_compressed().len() + uid.len() + 8);
+    buf.extend_from_slice(session.challenge.as_bytes());
+    buf.extend_from_slice(&g_r.to_compressed());
+    buf.extend_from_slice(uid.as_bytes());
+    buf.extend_from_slice(&req.t_u.to_be_bytes());
+    let h_i = G1Projective::hash::<ExpandMsgXmd<blake2::Blake2b512>>(&buf, TAG);
+
+    let item = BatchItem {
+        sigma_z: (G1Projective::from(sigma) + G1Projective::from(z)).into(),
+        h: h_i.into(),
+        pk,
+        g_r,
+    };
+    Ok((item, session))
+}
+
 #[derive(Debug, Clone)]
 pub struct GS {
     pub cfg: GSConfig,
-    pub pk_t: G2Affine,
+    /// TA key `pk_t`, prepared once for the pairing checks.
+    pub pk_t: Arc<G2Prepared>,
+    /// Current certificate of `cfg.pk_g1` issued by the TA, swapped whenever it is renewed.
+    pub cert: Arc<RwLock<GsCertificate>>,
+    pub policy: Arc<dyn GroupPolicy>,
+    pub crt_backend: CrtBackend,
+    pub shard_size: usize,
+    pub verify_pool: VerifyPool,
+    pub auth_coalescer: Option<AuthCoalescer>,
 }
 
 impl GS {
-    pub fn new(cfg: GSConfig, pk_t: G2Affine) -> Self {
-        Self { cfg, pk_t }
+    pub fn new(cfg: GSConfig, pk_t: G2Affine, cert: Arc<RwLock<GsCertificate>>, verify_pool: VerifyPool) -> Self {
+        Self {
+            cfg,
+            pk_t: Arc::new(G2Prepared::from(pk_t)),
+            cert,
+            verify_pool,
+            policy: Arc::new(OpenPolicy),
+            crt_backend: CrtBackend::default(),
+            shard_size: rpc::DEFAULT_SHARD_SIZE,
+            auth_coalescer: None,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: Arc<dyn GroupPolicy>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_crt_backend(mut self, crt_backend: CrtBackend) -> Self {
+        self.crt_backend = crt_backend;
+        self
+    }
+
+    pub fn with_shard_size(mut self, shard_size: usize) -> Self {
+        self.shard_size = shard_size;
+        self
+    }
+
+    /// Verify concurrent single phase 2 requests together, collecting them for `window`.
+    ///
+    /// Must be called within a tokio runtime.
+    pub fn with_auth_coalescing(mut self, window: Duration) -> Self {
+        self.auth_coalescer = Some(AuthCoalescer::spawn(self.pk_t.clone(), window, self.verify_pool.clone()));
+        self
+    }
+}
+
+/// Verify the phase 2 requests `reqs` together and establish a session for every accepted UAV.
+///
+/// CPU-heavy; runs on the [`VerifyPool`]. Returns one status per request, in order.
+pub(crate) fn verify_phase2(reqs: &[UavAuthRequest2], pk_t: &G2Prepared) -> Vec<BatchAuthStatus> {
+    let t_now = chrono::Utc::now().timestamp();
+    // requests that cannot take part in the aggregate check fail on their own
+    let prepared = reqs.par_iter().map(|req| prepare_phase2(req, t_now)).collect::<Vec<_>>();
+
+    let mut statuses = prepared
+        .iter()
+        .map(|p| p.as_ref().err().copied().unwrap_or(BatchAuthStatus::Ok))
+        .collect::<Vec<_>>();
+    let (idx, (items, sessions)): (Vec<_>, (Vec<_>, Vec<_>)) =
+        prepared.into_iter().enumerate().filter_map(|(i, p)| Some((i, p.ok()?))).unzip();
+    batch::record_rejected(reqs.len() - items.len());
+
+    let valid = batch::verify_batch(&items, pk_t);
+
+    idx.par_iter()
+        .zip(items.par_iter())
+        .zip(sessions.par_iter())
+        .zip(valid.par_iter())
+        .filter(|(_, ok)| **ok)
+        .for_each(|(((i, item), session), _)| {
+            let shared = G1Affine::from(G1Projective::from(item.g_r) * session.x);
+            let ssk_g_u = derive_session_key_from_g1(&shared);
+            secure::establish_session(&reqs[*i].uid, &ssk_g_u);
+        });
+    for (i, ok) in idx.into_iter().zip(valid) {
+        if !ok {
+            statuses[i] = BatchAuthStatus::InvalidSignature;
+        }
+    }
+    statuses
+}
+
+/// Run [`verify_phase2`] on `pool`; every request is answered `Busy` when its queue is full.
+///
+/// A refused job leaves the phase 1 sessions in place, so the UAVs may retry.
+pub(crate) async fn verify_phase2_on(pool: &VerifyPool, pk_t: Arc<G2Prepared>, reqs: Vec<UavAuthRequest2>) -> Vec<BatchAuthStatus> {
+    let count = reqs.len();
+    match pool.run(move || verify_phase2(&reqs, &pk_t)).await {
+        Some(statuses) => statuses,
+        None => {
+            batch::record_busy(count);
+            vec![BatchAuthStatus::Busy; count]
+        }
     }
 }
 
@@ -47,18 +172,14 @@ impl GsRpc for GS {
         let t_g = chrono::Utc::now().timestamp();
         let x = rand::random::<[u64; 4]>();
         let x = Scalar::from_raw_unchecked(x);
-        let x_point = G1Affine::generator() * x;
-        let z_point = G1Affine::from_compressed_hex(&uav_info.z).expect("Failed to decode UAV z value");
+        let x_point = g1_mul_generator(&x);
+        let z_point = uav_info.z;
+        let c = uav_info.challenge();
 
         let mut buf = Vec::with_capacity(
-            uav_info.c.len()
-                + uid.len()
-                + 8
-                + self.cfg.pk_g1.to_compressed().len()
-                + x_point.to_compressed().len()
-                + z_point.to_compressed().len(),
+            c.len() + uid.len() + 8 + self.cfg.pk_g1.to_compressed().len() + x_point.to_compressed().len() + z_point.to_compressed().len(),
         );
-        buf.extend_from_slice(uav_info.c.as_bytes());
+        buf.extend_from_slice(c.as_bytes());
         buf.extend_from_slice(uid.as_bytes());
         buf.extend_from_slice(&t_g.to_be_bytes());
         buf.extend_from_slice(&self.cfg.pk_g1.to_compressed());
@@ -67,65 +188,34 @@ impl GsRpc for GS {
         let e = hash_to_scalar(&buf);
         let sigma_g = x + e * self.cfg.sk;
 
-        AUTH_SESSIONS.insert(
-            uid.clone(),
-            AuthSession {
-                challenge: uav_info.c.clone(),
-                x,
-            },
-        );
+        AUTH_SESSIONS.insert(uid.clone(), AuthSession { challenge: c.clone(), x });
 
         Some(UavAuthResponse1 {
-            puf_challenge: uav_info.c.clone(),
+            puf_challenge: c,
             x: x_point.to_compressed().encode_hex::<String>(),
             sigma_g: sigma_g.to_be_bytes().encode_hex::<String>(),
             gs_pubkey: self.cfg.pk_g1.to_compressed().encode_hex::<String>(),
+            gs_cert: self.cert.read().unwrap().clone(),
             t_g,
         })
     }
 
-    async fn authenticate_uav_phase2(self, _context: ::tarpc::context::Context, req: UavAuthRequest2) -> Option<UavAuthResponse2> {
-        let uid = req.uid;
-        let (_, session) = AUTH_SESSIONS.remove(&uid)?;
-        let uav_info = UAV_LIST.0.get(&uid)?;
-
-        let pk_u = uav_info.pk;
-        let z = G1Affine::from_compressed_hex(&uav_info.z).expect("Failed to decode UAV z value");
-        let g_r = G1Affine::from_compressed_hex(&req.g_r).expect("Failed to decode g_r value");
-
-        let t_now = chrono::Utc::now().timestamp();
-        if (t_now - req.t_u).abs() > T_MAX {
-            tracing::warn!("UAV authentication request too old: {}", t_now - req.t_u);
-            return None;
-        }
-        let mut buf = Vec::with_capacity(session.challenge.len() + g_r.to_compressed().len() + uid.len() + 8);
-        buf.extend_from_slice(session.challenge.as_bytes());
-        buf.extend_from_slice(&g_r.to_compressed());
-        buf.extend_from_slice(uid.as_bytes());
-        buf.extend_from_slice(&req.t_u.to_be_bytes());
-
-        let sigma = req.sigma;
-        let sig = G1Affine::from_compressed_hex(&sigma).expect("Failed to decode UAV signature");
-
-        let h_i = G1Projective::hash::<ExpandMsgXmd<blake2::Blake2b512>>(&buf, TAG);
-
-        let tmp: G1Affine = (sig + G1Projective::from(z)).into();
-        let lhs = pairing(&tmp, &G2Affine::generator());
-
-        let rhs1 = pairing(&h_i.into(), &pk_u);
-        let rhs2 = pairing(&g_r, &self.pk_t);
-
-        let rhs = rhs1 * rhs2;
-        if lhs == rhs {
-            let shared = G1Affine::from(G1Projective::from(g_r) * session.x);
-            let ssk_g_u = derive_session_key_from_g1(&shared);
-            UAV_SESSION_KEYS.insert(uid.clone(), hex::encode(ssk_g_u));
-            info!("UAV authenticate successful {}", abbreviate_key_default(&uid));
-        } else {
-            tracing::warn!("UAV authenticate failed for uid: {}", abbreviate_key_default(&uid));
-            return None;
+    async fn authenticate_uav_phase2(
+        self,
+        _context: ::tarpc::context::Context,
+        req: UavAuthRequest2,
+    ) -> Result<UavAuthResponse2, BatchAuthStatus> {
+        let uid = req.uid.clone();
+        let status = match &self.auth_coalescer {
+            Some(coalescer) => coalescer.verify(req).await,
+            None => verify_phase2_on(&self.verify_pool, self.pk_t.clone(), vec![req]).await[0],
+        };
+        if status != BatchAuthStatus::Ok {
+            tracing::warn!("UAV authenticate failed for uid: {} ({:?})", abbreviate_key_default(&uid), status);
+            return Err(status);
         }
-        Some(UavAuthResponse2 {})
+        info!("UAV authenticate successful {}", abbreviate_key_default(&uid));
+        Ok(UavAuthResponse2 {})
     }
 
     async fn get_all_uav_id(self, _context: ::tarpc::context::Context, id: String) -> Vec<String> {
@@ -143,56 +233,108 @@ impl GsRpc for GS {
     }
 
     async fn communicate_uavs(self, _context: ::tarpc::context::Context, req: UavCommRequest) -> Option<UavCommResponse> {
-        let uid_k = req.uid_k;
-        let c_p = uid_k
-            .iter()
-            .map(|uid| {
-                let uav_opt = UAV_LIST.0.get(uid);
-                if uav_opt.is_none() {
-                    tracing::warn!("UAV with uid {} not found", abbreviate_key_default(&uid));
-                    return None;
-                }
-                let uav = uav_opt.unwrap();
-                Some((uid, uav.c.clone(), uav.p.clone()))
-            })
-            .collect::<Option<Vec<_>>>()?;
+        let (requester, uid_k) = (req.uid, req.uid_k);
+        let extra = uid_k.iter().map(|uid| uid.as_bytes()).collect::<Vec<_>>();
+        if !secure::verify_request(b"communicate_uavs", &requester, req.t_u, &extra, &req.mac) {
+            return None;
+        }
 
-        let c = c_p.iter().map(|(_, c, _)| c.clone()).collect::<Vec<_>>();
-        let p = c_p.iter().map(|(_, _, p)| p.clone()).collect::<Vec<_>>();
+        if !uid_k.contains(&requester) {
+            tracing::warn!("Requester {} is not a member of its group", abbreviate_key_default(&requester));
+            return None;
+        }
+        if !self.policy.allow(&requester, &uid_k) {
+            tracing::warn!("Group request denied by policy for uid: {}", abbreviate_key_default(&requester));
+            return None;
+        }
 
-        let bytes = rand::random::<[u8; 16]>();
-        let kd = rug::Integer::from_digits(&bytes, Order::MsfBe);
-        info!("UAV group key: {}", abbreviate_key_default(&kd.to_string_radix(16)));
+        // the requester gets its copy in the response, every other member through its mailbox
+        let group_id = hex::encode(rand::random::<[u8; 16]>());
+        let shards = group::shard_members(uid_k, self.shard_size, self.crt_backend)?;
+        let keys = group::distribute(&self.cfg, &group_id, 0, shards, Some(&requester))?;
+        group::response_for(&keys, &requester)
+    }
 
-        let eta = build_crt(p);
-        let mu = kd.clone() * eta;
+    async fn fetch_group_keys(self, _context: ::tarpc::context::Context, req: GroupKeyFetchRequest) -> Option<Vec<UavCommResponse>> {
+        if !secure::verify_request(b"fetch_group_keys", &req.uid, req.t_u, &[], &req.mac) {
+            return None;
+        }
+        Some(group::take(&req.uid))
+    }
 
-        Some(UavCommResponse {
-            mu: mu.to_string_radix(16),
-            c_m: c,
-        })
+    async fn create_group(self, _context: ::tarpc::context::Context, req: GroupCreateRequest) -> Option<UavCommResponse> {
+        let mut extra = vec![req.name.as_bytes()];
+        extra.extend(req.members.iter().map(|uid| uid.as_bytes()));
+        if !secure::verify_request(b"create_group", &req.uid, req.t_u, &extra, &req.mac) {
+            return None;
+        }
+        group::create(&self.cfg, self.policy.as_ref(), &req.name, &req.uid, req.members, self.shard_size)
     }
 
-    async fn batch_authenticate_uavs_phase1(self, _context: tarpc::context::Context, reqs: Vec<String>) -> Option<Vec<String>> {
-        let uids = reqs;
-        let responses = uids
-            .iter()
-            .map(|uid| {
-                let uav_info = UAV_LIST.0.get(uid)?;
+    async fn join_group(self, _context: ::tarpc::context::Context, req: GroupRequest) -> Option<UavCommResponse> {
+        if !secure::verify_request(b"join_group", &req.uid, req.t_u, &[req.name.as_bytes()], &req.mac) {
+            return None;
+        }
+        group::join(&self.cfg, self.policy.as_ref(), &req.name, &req.uid)
+    }
+
+    async fn leave_group(self, _context: ::tarpc::context::Context, req: GroupRequest) -> bool {
+        if !secure::verify_request(b"leave_group", &req.uid, req.t_u, &[req.name.as_bytes()], &req.mac) {
+            return false;
+        }
+        group::remove(&self.cfg, &req.name, &req.uid, &req.uid)
+    }
+
+    async fn evict_group_member(self, _context: ::tarpc::context::Context, req: GroupEvictRequest) -> bool {
+        let extra = [req.name.as_bytes(), req.target.as_bytes()];
+        if !secure::verify_request(b"evict_group_member", &req.uid, req.t_u, &extra, &req.mac) {
+            return false;
+        }
+        group::remove(&self.cfg, &req.name, &req.uid, &req.target)
+    }
+
+    async fn dissolve_group(self, _context: ::tarpc::context::Context, req: GroupRequest) -> bool {
+        if !secure::verify_request(b"dissolve_group", &req.uid, req.t_u, &[req.name.as_bytes()], &req.mac) {
+            return false;
+        }
+        group::dissolve(&req.name, &req.uid)
+    }
+
+    async fn get_group_epoch(self, _context: ::tarpc::context::Context, req: GroupRequest) -> Option<u64> {
+        if !secure::verify_request(b"get_group_epoch", &req.uid, req.t_u, &[req.name.as_bytes()], &req.mac) {
+            return None;
+        }
+        group::epoch(&req.name, &req.uid)
+    }
+
+    async fn batch_authenticate_uavs_phase1(self, _context: tarpc::context::Context, req: BatchAuthRequest1) -> BatchAuthResponse1 {
+        let gs_cert = self.cert.read().unwrap().clone();
+        let items = req
+            .reqs
+            .into_iter()
+            .map(|UavAuthRequest1 { uid }| {
+                let Some(uav_info) = UAV_LIST.0.get(&uid) else {
+                    return BatchAuthItem1 {
+                        uid,
+                        status: BatchAuthStatus::UnknownUav,
+                        response: None,
+                    };
+                };
+                let z_point = uav_info.z;
+                let c = uav_info.challenge();
                 let t_g = chrono::Utc::now().timestamp();
                 let x = rand::random::<[u64; 4]>();
                 let x = Scalar::from_raw_unchecked(x);
-                let x_point = G1Affine::generator() * x;
-                let z_point = G1Affine::from_compressed_hex(&uav_info.z).expect("Failed to decode UAV z value");
+                let x_point = g1_mul_generator(&x);
                 let mut buf = Vec::with_capacity(
-                    uav_info.c.len()
+                    c.len()
                         + uid.len()
                         + 8
                         + self.cfg.pk_g1.to_compressed().len()
                         + x_point.to_compressed().len()
                         + z_point.to_compressed().len(),
                 );
-                buf.extend_from_slice(uav_info.c.as_bytes());
+                buf.extend_from_slice(c.as_bytes());
                 buf.extend_from_slice(uid.as_bytes());
                 buf.extend_from_slice(&t_g.to_be_bytes());
                 buf.extend_from_slice(&self.cfg.pk_g1.to_compressed());
@@ -201,107 +343,56 @@ impl GsRpc for GS {
                 let e = hash_to_scalar(&buf);
                 let sigma_g = x + e * self.cfg.sk;
 
-                AUTH_SESSIONS.insert(
-                    uid.clone(),
-                    AuthSession {
-                        challenge: uav_info.c.clone(),
-                        x,
-                    },
-                );
+                AUTH_SESSIONS.insert(uid.clone(), AuthSession { challenge: c.clone(), x });
 
                 let response = UavAuthResponse1 {
-                    puf_challenge: uav_info.c.clone(),
+                    puf_challenge: c,
                     x: x_point.to_compressed().encode_hex::<String>(),
                     sigma_g: sigma_g.to_be_bytes().encode_hex::<String>(),
                     gs_pubkey: self.cfg.pk_g1.to_compressed().encode_hex::<String>(),
+                    gs_cert: gs_cert.clone(),
                     t_g,
                 };
-                Some(serde_json::to_string(&response).expect("Failed to encode auth response"))
+                BatchAuthItem1 {
+                    uid,
+                    status: BatchAuthStatus::Ok,
+                    response: Some(response),
+                }
             })
-            .collect::<Option<Vec<_>>>()?;
-        Some(responses)
+            .collect();
+        BatchAuthResponse1 { items }
     }
-    async fn batch_authenticate_uavs_phase2(
-        self,
-        _context: tarpc::context::Context,
-        reqs: Vec<UavAuthRequest2>,
-    ) -> Option<UavAuthResponse2> {
-        let uav_infos = reqs
-            .par_iter()
-            .map(|req| -> Option<(UavInfo, AuthSession)> {
-                let uid = &req.uid;
-                let uav_info = UAV_LIST.0.get(uid)?.clone();
-                let (_, session) = AUTH_SESSIONS.remove(uid)?;
-                Some((uav_info, session))
-            })
-            .collect::<Option<Vec<_>>>()?;
-
-        let pk_us = uav_infos.par_iter().map(|(uav_info, _)| uav_info.pk).collect::<Vec<_>>();
-        let z = uav_infos
-            .par_iter()
-            .map(|(uav_info, _)| G1Affine::from_compressed_hex(&uav_info.z).expect("Failed to decode UAV z value"))
-            .map(G1Projective::from)
-            .reduce(G1Projective::identity, |acc, z| acc + z);
-
-        let g_rs = reqs
-            .par_iter()
-            .map(|req| G1Affine::from_compressed_hex(&req.g_r).expect("Failed to decode g_r value"))
-            .collect::<Vec<_>>();
 
-        let t_now = chrono::Utc::now().timestamp();
-        // check if the request is too old
-        reqs.par_iter()
-            .map(|req| {
-                if (t_now - req.t_u).abs() > T_MAX {
-                    tracing::warn!("UAV authentication request too old: {}", t_now - req.t_u);
-                    return None;
-                }
-                Some(())
-            })
-            .collect::<Option<Vec<_>>>()?;
-
-        let sigma = reqs
-            .par_iter()
-            .map(|req| G1Affine::from_compressed_hex(&req.sigma).expect("Failed to decode UAV signature"))
-            .map(G1Projective::from)
-            .reduce(G1Projective::identity, |acc, sig| acc + sig);
-
-        let h_is = reqs
-            .par_iter()
-            .zip(uav_infos.par_iter())
-            .map(|(req, (_uav_info, session))| {
-                let uid = &req.uid;
-                let g_r = G1Affine::from_compressed_hex(&req.g_r).expect("Failed to decode g_r value");
-                let mut buf = Vec::with_capacity(session.challenge.len() + g_r.to_compressed().len() + uid.len() + 8);
-                buf.extend_from_slice(session.challenge.as_bytes());
-                buf.extend_from_slice(&g_r.to_compressed());
-                buf.extend_from_slice(uid.as_bytes());
-                buf.extend_from_slice(&req.t_u.to_be_bytes());
-                G1Projective::hash::<ExpandMsgXmd<blake2::Blake2b512>>(&buf, TAG)
-            })
-            .map(G1Affine::from)
-            .collect::<Vec<_>>();
+    async fn batch_authenticate_uavs_phase2(self, _context: tarpc::context::Context, req: BatchAuthRequest2) -> BatchAuthResponse2 {
+        let uids = req.reqs.iter().map(|req| req.uid.clone()).collect::<Vec<_>>();
+        let statuses = verify_phase2_on(&self.verify_pool, self.pk_t.clone(), req.reqs).await;
 
-        let tmp: G1Affine = (sigma + z).into();
-        let lhs = pairing(&tmp, &G2Affine::generator());
-
-        let pk_t_prepared = G2Prepared::from(self.pk_t);
-        let pk_us_prepared = pk_us.par_iter().map(|pk_u| G2Prepared::from(*pk_u)).collect::<Vec<_>>();
-        let g_r_terms = g_rs.iter().map(|g_r| (g_r, &pk_t_prepared));
-        let h_i_terms = h_is.iter().zip(pk_us_prepared.iter());
-        let terms = h_i_terms.chain(g_r_terms).collect::<Vec<_>>();
-        let rhs = multi_miller_loop(&terms).final_exponentiation();
-
-        if lhs == rhs {
-            reqs.par_iter().zip(g_rs.par_iter()).zip(uav_infos.par_iter()).for_each(|((req, g_r), (_, session))| {
-                let shared = G1Affine::from(G1Projective::from(*g_r) * session.x);
-                let ssk_g_u = derive_session_key_from_g1(&shared);
-                UAV_SESSION_KEYS.insert(req.uid.clone(), hex::encode(ssk_g_u));
-            });
+        let accepted = statuses.iter().filter(|s| **s == BatchAuthStatus::Ok).count();
+        if accepted == uids.len() {
             info!("UAV batch authentication successful");
-            return Some(UavAuthResponse2 {});
+        } else {
+            tracing::warn!("UAV batch authentication accepted {}/{} UAVs", accepted, uids.len());
+        }
+        let items = uids
+            .into_iter()
+            .zip(statuses)
+            .map(|(uid, status)| BatchAuthItem2 { uid, status })
+            .collect();
+        BatchAuthResponse2 { items }
+    }
+
+    async fn get_batch_auth_metrics(self, _context: tarpc::context::Context) -> BatchAuthMetrics {
+        batch::metrics()
+    }
+
+    async fn send_secure(self, _context: tarpc::context::Context, uid: String, seq: u64, ciphertext: Vec<u8>) -> bool {
+        secure::receive(&uid, seq, &ciphertext)
+    }
+
+    async fn poll_secure(self, _context: tarpc::context::Context, req: SecurePollRequest) -> Option<Vec<SecureFrame>> {
+        if !secure::verify_request(b"poll_secure", &req.uid, req.t_u, &[], &req.mac) {
+            return None;
         }
-        tracing::warn!("UAV batch authentication failed");
-        None
+        Some(secure::poll(&req.uid))
     }
 }
@@ -0,0 +1,190 @@
+use crate::UAV_SESSION_KEYS;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use rpc::{SecureFrame, REQUEST_WINDOW_MS};
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicI64, Ordering},
+};
+use tracing::{info, warn};
+use utils::{abbreviate_key_default, verify_session_mac, Direction, SecureChannel};
+
+/// Upper bound of undelivered frames kept per UAV.
+const OUTBOX_CAPACITY: usize = 256;
+/// Default lifetime of a session key in seconds; `GS_SESSION_TTL` overrides it.
+pub(crate) const DEFAULT_SESSION_TTL: i64 = 3600;
+
+static SESSION_TTL: AtomicI64 = AtomicI64::new(DEFAULT_SESSION_TTL);
+
+/// Session key of an authenticated UAV, the Unix time after which it is no longer accepted and
+/// the `t_u` of the last request accepted under it.
+#[derive(Debug)]
+pub struct SessionKey {
+    key: [u8; 16],
+    expires_at: i64,
+    last_t_u: i64,
+}
+
+lazy_static! {
+    static ref SECURE_CHANNELS: DashMap<String, SecureChannel> = DashMap::new();
+    static ref SECURE_OUTBOX: DashMap<String, VecDeque<SecureFrame>> = DashMap::new();
+}
+
+pub(crate) fn set_session_ttl(secs: i64) {
+    SESSION_TTL.store(secs, Ordering::Relaxed);
+}
+
+/// Record the session key of a freshly authenticated UAV and reset its secure channel.
+pub(crate) fn establish_session(uid: &str, ssk_g_u: &[u8; 16]) {
+    let session = SessionKey {
+        key: *ssk_g_u,
+        expires_at: chrono::Utc::now().timestamp() + SESSION_TTL.load(Ordering::Relaxed),
+        last_t_u: i64::MIN,
+    };
+    UAV_SESSION_KEYS.insert(uid.to_string(), session);
+    SECURE_CHANNELS.insert(uid.to_string(), SecureChannel::new(uid, ssk_g_u, Direction::GsToUav));
+    SECURE_OUTBOX.remove(uid);
+}
+
+/// Session key of `uid` if it currently holds an authenticated session.
+///
+/// An expired session is dropped together with its secure channel, so the UAV has to authenticate again.
+pub(crate) fn session_key(uid: &str) -> Option<[u8; 16]> {
+    let t_now = chrono::Utc::now().timestamp();
+    if UAV_SESSION_KEYS.remove_if(uid, |_, session| session.expires_at < t_now).is_some() {
+        SECURE_CHANNELS.remove(uid);
+        SECURE_OUTBOX.remove(uid);
+        info!("Session of {} expired", abbreviate_key_default(uid));
+        return None;
+    }
+    UAV_SESSION_KEYS.get(uid).map(|session| session.key)
+}
+
+/// Check that `uid` holds an active session and that `mac` covers `label`, `uid`, `t_u` and `extra`.
+///
+/// `t_u` must be newer than that of every request accepted before in the session, so a captured
+/// request cannot be replayed within the freshness window.
+pub(crate) fn verify_request(label: &[u8], uid: &str, t_u: i64, extra: &[&[u8]], mac: &str) -> bool {
+    let Some(ssk_g_u) = session_key(uid) else {
+        warn!("Request from UAV without session: {}", abbreviate_key_default(uid));
+        return false;
+    };
+
+    let t_now = chrono::Utc::now().timestamp_millis();
+    if (t_now - t_u).abs() > REQUEST_WINDOW_MS {
+        warn!("UAV request too old: {} ms", t_now - t_u);
+        return false;
+    }
+
+    let Ok(mac) = hex::decode(mac) else {
+        return false;
+    };
+    let t_u_bytes = t_u.to_be_bytes();
+    let mut parts: Vec<&[u8]> = Vec::with_capacity(extra.len() + 3);
+    parts.push(label);
+    parts.push(uid.as_bytes());
+    parts.push(&t_u_bytes);
+    parts.extend_from_slice(extra);
+    if !verify_session_mac(&ssk_g_u, &parts, &mac) {
+        warn!("Request MAC invalid for uid: {}", abbreviate_key_default(uid));
+        return false;
+    }
+
+    // only a request with a valid MAC moves the mark, and only under the key it was made with
+    let Some(mut session) = UAV_SESSION_KEYS.get_mut(uid).filter(|session| session.key == ssk_g_u) else {
+        return false;
+    };
+    if t_u <= session.last_t_u {
+        warn!("Replayed request from {}", abbreviate_key_default(uid));
+        return false;
+    }
+    session.last_t_u = t_u;
+    true
+}
+
+/// Decrypt an uplink frame and acknowledge it on the downlink.
+pub(crate) fn receive(uid: &str, seq: u64, ciphertext: &[u8]) -> bool {
+    if session_key(uid).is_none() {
+        warn!("Secure frame from UAV without session: {}", abbreviate_key_default(uid));
+        return false;
+    }
+    let Some(mut channel) = SECURE_CHANNELS.get_mut(uid) else {
+        warn!("Secure frame from UAV without session: {}", abbreviate_key_default(uid));
+        return false;
+    };
+    let plaintext = match channel.open(seq, ciphertext) {
+        Ok(plaintext) => plaintext,
+        Err(e) => {
+            warn!("Secure frame rejected for {}: {}", abbreviate_key_default(uid), e);
+            return false;
+        }
+    };
+    drop(channel);
+    info!(
+        "Secure frame {} from {} ({} bytes)",
+        seq,
+        abbreviate_key_default(uid),
+        plaintext.len()
+    );
+    send(uid, &seq.to_be_bytes())
+}
+
+/// Encrypt `plaintext` for `uid` and queue it until the UAV polls.
+pub(crate) fn send(uid: &str, plaintext: &[u8]) -> bool {
+    let Some(mut channel) = SECURE_CHANNELS.get_mut(uid) else {
+        return false;
+    };
+    let (seq, ciphertext) = match channel.seal(plaintext) {
+        Ok(frame) => frame,
+        Err(e) => {
+            warn!("Failed to seal frame for {}: {}", abbreviate_key_default(uid), e);
+            return false;
+        }
+    };
+    drop(channel);
+    let mut outbox = SECURE_OUTBOX.entry(uid.to_string()).or_default();
+    if outbox.len() == OUTBOX_CAPACITY {
+        outbox.pop_front();
+    }
+    outbox.push_back(SecureFrame { seq, ciphertext });
+    true
+}
+
+/// Take every queued downlink frame of `uid`.
+pub(crate) fn poll(uid: &str) -> Vec<SecureFrame> {
+    SECURE_OUTBOX
+        .get_mut(uid)
+        .map(|mut outbox| outbox.drain(..).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_session_is_dropped() {
+        establish_session("fresh", &[1; 16]);
+        assert_eq!(session_key("fresh"), Some([1; 16]));
+
+        establish_session("stale", &[2; 16]);
+        UAV_SESSION_KEYS.get_mut("stale").unwrap().expires_at = chrono::Utc::now().timestamp() - 1;
+        assert_eq!(session_key("stale"), None);
+        assert!(!UAV_SESSION_KEYS.contains_key("stale"));
+        assert!(!SECURE_CHANNELS.contains_key("stale"));
+        assert!(!receive("stale", 0, &[0; 32]));
+    }
+
+    #[test]
+    fn test_replayed_request_is_rejected() {
+        let key = [3; 16];
+        establish_session("replay", &key);
+        let mac = |t_u: i64| hex::encode(utils::session_mac(&key, &[b"test", b"replay", &t_u.to_be_bytes(), b"extra"]));
+        let t_u = chrono::Utc::now().timestamp_millis();
+
+        assert!(verify_request(b"test", "replay", t_u, &[b"extra"], &mac(t_u)));
+        assert!(!verify_request(b"test", "replay", t_u, &[b"extra"], &mac(t_u)));
+        assert!(!verify_request(b"test", "replay", t_u - 1, &[b"extra"], &mac(t_u - 1)));
+        assert!(verify_request(b"test", "replay", t_u + 1, &[b"extra"], &mac(t_u + 1)));
+    }
+}
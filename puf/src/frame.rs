@@ -0,0 +1,156 @@
+//! Frames of the serial PUF protocol.
+//!
+//! Requests and replies share one layout: `SYNC | len | seq | code | payload | crc`, where `len`
+//! counts the payload bytes and `crc` is the big-endian CRC-16/CCITT-FALSE of everything between
+//! `SYNC` and `crc`. `code` is the command of a request and the status of a reply; the reply echoes
+//! the `seq` of its request, so a late reply to an abandoned request is told apart.
+
+pub(crate) const SYNC: u8 = 0xA5;
+/// Longest payload a frame may carry; a longer `len` marks a false sync byte.
+pub(crate) const MAX_PAYLOAD: usize = 32;
+const HEADER_LEN: usize = 4;
+const CRC_LEN: usize = 2;
+
+/// Evaluate the PUF on the challenge in the payload.
+pub(crate) const CMD_EVAL: u8 = 0x01;
+/// Report the challenge and response widths in bits, as two big-endian `u16`s.
+pub(crate) const CMD_INFO: u8 = 0x02;
+
+pub(crate) const STATUS_OK: u8 = 0x00;
+/// The board received a request with a wrong CRC.
+pub(crate) const STATUS_BAD_CRC: u8 = 0x01;
+/// The payload has the wrong length for the command.
+pub(crate) const STATUS_BAD_LENGTH: u8 = 0x02;
+/// The PUF is still evaluating an earlier challenge.
+pub(crate) const STATUS_BUSY: u8 = 0x03;
+pub(crate) const STATUS_BAD_COMMAND: u8 = 0x04;
+
+pub(crate) fn status_name(status: u8) -> String {
+    match status {
+        STATUS_OK => "ok".to_string(),
+        STATUS_BAD_CRC => "bad CRC".to_string(),
+        STATUS_BAD_LENGTH => "bad length".to_string(),
+        STATUS_BUSY => "busy".to_string(),
+        STATUS_BAD_COMMAND => "unknown command".to_string(),
+        other => format!("status {other:#04x}"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Frame {
+    pub seq: u8,
+    pub code: u8,
+    pub payload: Vec<u8>,
+}
+
+enum Parse {
+    Frame(Frame, usize),
+    Incomplete,
+    Invalid,
+}
+
+impl Frame {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        assert!(self.payload.len() <= MAX_PAYLOAD, "frame payload too long");
+        let mut out = Vec::with_capacity(HEADER_LEN + self.payload.len() + CRC_LEN);
+        out.extend([SYNC, self.payload.len() as u8, self.seq, self.code]);
+        out.extend(&self.payload);
+        out.extend(crc16(&out[1..]).to_be_bytes());
+        out
+    }
+
+    /// Take the first valid frame out of `buf`, or `None` until more bytes arrive.
+    ///
+    /// Bytes before the frame are dropped, and so is every sync byte whose frame fails the length
+    /// or CRC check, so the decoder finds its way back to the frame boundaries after line noise.
+    pub(crate) fn decode(buf: &mut Vec<u8>) -> Option<Frame> {
+        let mut keep = None;
+        let mut start = 0;
+        while let Some(i) = buf[start..].iter().position(|&b| b == SYNC) {
+            let at = start + i;
+            match parse(&buf[at..]) {
+                Parse::Frame(frame, len) => {
+                    buf.drain(..at + len);
+                    return Some(frame);
+                }
+                Parse::Incomplete => {
+                    keep.get_or_insert(at);
+                }
+                Parse::Invalid => {}
+            }
+            start = at + 1;
+        }
+        match keep {
+            Some(at) => drop(buf.drain(..at)),
+            None => buf.clear(),
+        }
+        None
+    }
+}
+
+/// Parse the frame at the start of `buf`, which begins with [`SYNC`].
+fn parse(buf: &[u8]) -> Parse {
+    if buf.len() < 2 {
+        return Parse::Incomplete;
+    }
+    let len = buf[1] as usize;
+    if len > MAX_PAYLOAD {
+        return Parse::Invalid;
+    }
+    let total = HEADER_LEN + len + CRC_LEN;
+    if buf.len() < total {
+        return Parse::Incomplete;
+    }
+    if crc16(&buf[1..total - CRC_LEN]).to_be_bytes() != buf[total - CRC_LEN..total] {
+        return Parse::Invalid;
+    }
+    let frame = Frame {
+        seq: buf[2],
+        code: buf[3],
+        payload: buf[HEADER_LEN..total - CRC_LEN].to_vec(),
+    };
+    Parse::Frame(frame, total)
+}
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF.
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |mut crc, &b| {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_resyncs_after_noise() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+
+        let frame = Frame {
+            seq: 7,
+            code: CMD_EVAL,
+            payload: (0..12).collect(),
+        };
+        let encoded = frame.encode();
+
+        // garbage, a sync byte with an oversized length, a frame with a broken CRC
+        let mut corrupted = encoded.clone();
+        corrupted[5] ^= 0xFF;
+        let mut buf = vec![0x00, 0x13, SYNC, 0xFF];
+        buf.extend(&corrupted);
+        buf.extend(&encoded);
+        assert_eq!(Frame::decode(&mut buf), Some(frame.clone()));
+        assert!(buf.is_empty());
+
+        // a frame split across reads
+        let mut buf = encoded[..9].to_vec();
+        assert_eq!(Frame::decode(&mut buf), None);
+        buf.extend(&encoded[9..]);
+        assert_eq!(Frame::decode(&mut buf), Some(frame));
+    }
+}
@@ -0,0 +1,272 @@
+mod frame;
+mod majority;
+mod serial;
+mod sim;
+mod tcp;
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+pub use majority::{MajorityPuf, Reliability, UNSTABLE_RATE};
+pub use serial::{SerialConfig, SerialProtocol, SerialPuf};
+pub use sim::SimPuf;
+pub use tcp::{PoolMetrics, TcpPoolConfig, TcpPuf};
+pub use utils::{MAX_PUF_BITS as MAX_BITS, PufEncoding, PufGeometry};
+
+/// Baud rate of a serial PUF when the URL does not give one.
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+/// A physical or simulated PUF answering challenges with responses of a fixed [`PufGeometry`].
+#[async_trait::async_trait]
+pub trait PufBackend: Send + Sync {
+    /// Response to `challenge`, both as hex in the widths and encoding of [`PufBackend::geometry`].
+    async fn calculate(&self, challenge: &str) -> anyhow::Result<String>;
+
+    /// Widths and response encoding this backend answers with.
+    fn geometry(&self) -> PufGeometry;
+
+    /// Bit stability statistics, for backends that take repeated readings.
+    fn reliability(&self) -> Option<Reliability> {
+        None
+    }
+
+    /// Connection pool counters, for backends that keep a pool.
+    fn pool_metrics(&self) -> Option<PoolMetrics> {
+        None
+    }
+}
+
+/// Where to find the PUF, parsed from `tcp://host:port?timeout_ms=2000&connect_timeout_ms=1000&retries=5`,
+/// `serial:///dev/ttyUSB0?baud=115200&protocol=raw&timeout_ms=500&retries=3` or `sim:seed?k=1&noise=0`.
+///
+/// Every backend also takes `challenge_bits=96&response_bits=96&encoding=hex-upper`; a framed
+/// serial board that reports its own geometry overrides them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PufSpec {
+    Tcp {
+        addr: String,
+        config: TcpPoolConfig,
+        geometry: PufGeometry,
+    },
+    Serial {
+        path: String,
+        config: SerialConfig,
+        geometry: PufGeometry,
+    },
+    Sim {
+        seed: u64,
+        k: usize,
+        noise: f64,
+        geometry: PufGeometry,
+    },
+}
+
+impl PufSpec {
+    /// Open the backend; `pool_size` bounds the number of TCP connections and is ignored otherwise.
+    pub async fn connect(&self, pool_size: usize) -> anyhow::Result<Arc<dyn PufBackend>> {
+        Ok(match self {
+            PufSpec::Tcp { addr, config, geometry } => {
+                let config = TcpPoolConfig {
+                    max_size: pool_size,
+                    ..config.clone()
+                };
+                Arc::new(TcpPuf::with_config(addr, config).await?.with_geometry(*geometry)?)
+            }
+            PufSpec::Serial { path, config, geometry } => Arc::new(SerialPuf::connect(path, config.clone(), *geometry).await?),
+            PufSpec::Sim { seed, k, noise, geometry } => Arc::new(SimPuf::new(*seed, *k, *noise)?.with_geometry(*geometry)?),
+        })
+    }
+}
+
+impl FromStr for PufSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if let Some(rest) = s.strip_prefix("tcp://") {
+            let (addr, query) = rest.split_once('?').unwrap_or((rest, ""));
+            let mut config = TcpPoolConfig::default();
+            let mut geometry = PufGeometry::default();
+            for param in query_params(query) {
+                if geometry_param(&mut geometry, param)? {
+                    continue;
+                }
+                match param {
+                    ("timeout_ms", value) => config.request_timeout = Duration::from_millis(value.parse()?),
+                    ("connect_timeout_ms", value) => config.connect_timeout = Duration::from_millis(value.parse()?),
+                    ("retries", value) => config.retries = value.parse()?,
+                    (key, _) => anyhow::bail!("Unknown TCP PUF parameter: {key}"),
+                }
+            }
+            geometry.validate()?;
+            return Ok(PufSpec::Tcp {
+                addr: addr.to_string(),
+                config,
+                geometry,
+            });
+        }
+        if let Some(rest) = s.strip_prefix("serial://") {
+            let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+            let mut config = SerialConfig::default();
+            let mut geometry = PufGeometry::default();
+            for param in query_params(query) {
+                if geometry_param(&mut geometry, param)? {
+                    continue;
+                }
+                match param {
+                    ("baud", value) => config.baud = value.parse()?,
+                    ("protocol", value) => config.protocol = value.parse()?,
+                    ("timeout_ms", value) => config.timeout = Duration::from_millis(value.parse()?),
+                    ("retries", value) => config.retries = value.parse()?,
+                    (key, _) => anyhow::bail!("Unknown serial PUF parameter: {key}"),
+                }
+            }
+            if path.is_empty() {
+                anyhow::bail!("Serial PUF URL without a device path: {s}");
+            }
+            geometry.validate()?;
+            return Ok(PufSpec::Serial {
+                path: path.to_string(),
+                config,
+                geometry,
+            });
+        }
+        if let Some(rest) = s.strip_prefix("sim:") {
+            let (seed, query) = rest.split_once('?').unwrap_or((rest, ""));
+            let (mut k, mut noise) = (1, 0.0);
+            let mut geometry = PufGeometry::default();
+            for param in query_params(query) {
+                if geometry_param(&mut geometry, param)? {
+                    continue;
+                }
+                match param {
+                    ("k", value) => k = value.parse()?,
+                    ("noise", value) => noise = value.parse()?,
+                    (key, _) => anyhow::bail!("Unknown simulated PUF parameter: {key}"),
+                }
+            }
+            geometry.validate()?;
+            return Ok(PufSpec::Sim {
+                seed: seed.parse()?,
+                k,
+                noise,
+                geometry,
+            });
+        }
+        anyhow::bail!("Unknown PUF backend: {s} (expected tcp://host:port, serial:///dev/tty...?baud=N or sim:seed)")
+    }
+}
+
+/// `key=value` pairs of a URL query; a pair without `=` has an empty value.
+fn query_params(query: &str) -> impl Iterator<Item = (&str, &str)> {
+    query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| p.split_once('=').unwrap_or((p, "")))
+}
+
+/// Apply a query parameter to `geometry`; `false` if it is not a geometry parameter.
+fn geometry_param(geometry: &mut PufGeometry, param: (&str, &str)) -> anyhow::Result<bool> {
+    match param {
+        ("challenge_bits", value) => geometry.challenge_bits = value.parse()?,
+        ("response_bits", value) => geometry.response_bits = value.parse()?,
+        ("encoding", value) => geometry.encoding = value.parse()?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            "tcp://127.0.0.1:12345".parse::<PufSpec>().unwrap(),
+            PufSpec::Tcp {
+                addr: "127.0.0.1:12345".to_string(),
+                config: TcpPoolConfig::default(),
+                geometry: PufGeometry::default()
+            }
+        );
+        assert_eq!(
+            "tcp://127.0.0.1:12345?timeout_ms=50&retries=0".parse::<PufSpec>().unwrap(),
+            PufSpec::Tcp {
+                addr: "127.0.0.1:12345".to_string(),
+                config: TcpPoolConfig {
+                    request_timeout: Duration::from_millis(50),
+                    retries: 0,
+                    ..TcpPoolConfig::default()
+                },
+                geometry: PufGeometry::default()
+            }
+        );
+        assert_eq!(
+            "serial:///dev/ttyUSB0?baud=9600&protocol=framed&timeout_ms=100&retries=1"
+                .parse::<PufSpec>()
+                .unwrap(),
+            PufSpec::Serial {
+                path: "/dev/ttyUSB0".to_string(),
+                config: SerialConfig {
+                    baud: 9600,
+                    protocol: SerialProtocol::Framed,
+                    timeout: Duration::from_millis(100),
+                    retries: 1
+                },
+                geometry: PufGeometry::default()
+            }
+        );
+        assert_eq!(
+            "serial:///dev/ttyUSB0".parse::<PufSpec>().unwrap(),
+            PufSpec::Serial {
+                path: "/dev/ttyUSB0".to_string(),
+                config: SerialConfig {
+                    baud: 115200,
+                    protocol: SerialProtocol::Raw,
+                    timeout: Duration::from_millis(500),
+                    retries: 3
+                },
+                geometry: PufGeometry::default()
+            }
+        );
+        assert_eq!(
+            "sim:42".parse::<PufSpec>().unwrap(),
+            PufSpec::Sim {
+                seed: 42,
+                k: 1,
+                noise: 0.0,
+                geometry: PufGeometry::default()
+            }
+        );
+        assert_eq!(
+            "sim:7?k=4&noise=0.05&challenge_bits=64&response_bits=256&encoding=hex-lower"
+                .parse::<PufSpec>()
+                .unwrap(),
+            PufSpec::Sim {
+                seed: 7,
+                k: 4,
+                noise: 0.05,
+                geometry: PufGeometry {
+                    challenge_bits: 64,
+                    response_bits: 256,
+                    encoding: PufEncoding::HexLower
+                }
+            }
+        );
+        assert!("tcp://127.0.0.1:12345?response_bits=100".parse::<PufSpec>().is_err());
+        assert!("sim:1?encoding=base64".parse::<PufSpec>().is_err());
+        assert!("sim:seven".parse::<PufSpec>().is_err());
+        assert!("serial:///dev/ttyUSB0?parity=odd".parse::<PufSpec>().is_err());
+        assert!("udp://127.0.0.1:1".parse::<PufSpec>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sim_is_deterministic_per_seed() {
+        let (a, b) = (SimPuf::new(1, 1, 0.0).unwrap(), SimPuf::new(2, 1, 0.0).unwrap());
+        let c = "00112233445566778899aabb";
+        let r = a.calculate(c).await.unwrap();
+        assert_eq!(r.len(), 24);
+        assert_eq!(r, a.calculate(c).await.unwrap());
+        assert_ne!(r, b.calculate(c).await.unwrap());
+        assert!(a.calculate("0011").await.is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{PoolMetrics, PufBackend, PufGeometry};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Share of readings of a bit position that may disagree with the vote before it counts as unstable.
+pub const UNSTABLE_RATE: f64 = 0.05;
+
+/// Reads the PUF `reads` times per challenge and takes the bitwise majority.
+///
+/// Every reading that loses the vote on a bit counts against that bit position, so positions that
+/// flip more often than [`UNSTABLE_RATE`] show up in [`Reliability::unstable_bits`].
+pub struct MajorityPuf {
+    inner: Arc<dyn PufBackend>,
+    reads: usize,
+    stats: Mutex<BitStats>,
+}
+
+struct BitStats {
+    challenges: u64,
+    /// Challenges whose readings were not unanimous.
+    split: u64,
+    /// Readings that lost the vote, per bit position.
+    flips: Vec<u64>,
+}
+
+/// Bit stability seen by a [`MajorityPuf`] so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reliability {
+    pub reads: usize,
+    pub challenges: u64,
+    pub split_challenges: u64,
+    /// Share of all read bits that lost the vote.
+    pub flip_rate: f64,
+    /// Bit positions, most significant first, that flip more often than [`UNSTABLE_RATE`].
+    pub unstable_bits: Vec<usize>,
+}
+
+impl fmt::Display for Reliability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} reads per challenge, {}/{} challenges with disagreeing reads, bit flip rate {:.4}, {} unstable bits {:?}",
+            self.reads,
+            self.split_challenges,
+            self.challenges,
+            self.flip_rate,
+            self.unstable_bits.len(),
+            self.unstable_bits
+        )
+    }
+}
+
+impl MajorityPuf {
+    /// `reads` must be odd so that every vote has a winner.
+    pub fn new(inner: Arc<dyn PufBackend>, reads: usize) -> anyhow::Result<Self> {
+        if reads.is_multiple_of(2) {
+            anyhow::bail!("PUF reads per challenge must be odd, got {reads}");
+        }
+        let stats = BitStats {
+            challenges: 0,
+            split: 0,
+            flips: vec![0; inner.geometry().response_bits],
+        };
+        Ok(Self {
+            inner,
+            reads,
+            stats: Mutex::new(stats),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl PufBackend for MajorityPuf {
+    async fn calculate(&self, challenge: &str) -> anyhow::Result<String> {
+        let geometry = self.inner.geometry();
+        let mut readings = Vec::with_capacity(self.reads);
+        for _ in 0..self.reads {
+            readings.push(geometry.decode_response(&self.inner.calculate(challenge).await?)?);
+        }
+
+        let mut voted = vec![0u8; geometry.response_bytes()];
+        let mut stats = self.stats.lock().unwrap();
+        let mut split = false;
+        for i in 0..geometry.response_bits {
+            let ones = readings.iter().filter(|r| (r[i / 8] >> (7 - i % 8)) & 1 == 1).count();
+            let one = ones * 2 > self.reads;
+            let losers = if one { self.reads - ones } else { ones };
+            voted[i / 8] |= (one as u8) << (7 - i % 8);
+            stats.flips[i] += losers as u64;
+            split |= losers > 0;
+        }
+        stats.challenges += 1;
+        stats.split += split as u64;
+        Ok(geometry.encode_response(&voted))
+    }
+
+    fn geometry(&self) -> PufGeometry {
+        self.inner.geometry()
+    }
+
+    fn pool_metrics(&self) -> Option<PoolMetrics> {
+        self.inner.pool_metrics()
+    }
+
+    fn reliability(&self) -> Option<Reliability> {
+        let stats = self.stats.lock().unwrap();
+        let readings = (stats.challenges * self.reads as u64).max(1) as f64;
+        Some(Reliability {
+            reads: self.reads,
+            challenges: stats.challenges,
+            split_challenges: stats.split,
+            flip_rate: stats.flips.iter().sum::<u64>() as f64 / (readings * stats.flips.len() as f64),
+            unstable_bits: (0..stats.flips.len())
+                .filter(|&i| stats.flips[i] as f64 / readings > UNSTABLE_RATE)
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimPuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers every challenge with zeros, except that bit 5 is set on every third reading.
+    struct FlakyPuf(AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl PufBackend for FlakyPuf {
+        async fn calculate(&self, _: &str) -> anyhow::Result<String> {
+            let mut r = [0u8; 12];
+            if self.0.fetch_add(1, Ordering::Relaxed).is_multiple_of(3) {
+                r[0] = 1 << 2;
+            }
+            Ok(hex::encode_upper(r))
+        }
+
+        fn geometry(&self) -> PufGeometry {
+            PufGeometry::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_majority_flags_unstable_bits() {
+        let puf = MajorityPuf::new(Arc::new(FlakyPuf(AtomicUsize::new(0))), 3).unwrap();
+        for _ in 0..10 {
+            assert_eq!(puf.calculate("00112233445566778899aabb").await.unwrap(), "0".repeat(24));
+        }
+        let report = puf.reliability().unwrap();
+        assert_eq!((report.challenges, report.split_challenges), (10, 10));
+        assert_eq!(report.unstable_bits, vec![5]);
+        assert!(MajorityPuf::new(Arc::new(FlakyPuf(AtomicUsize::new(0))), 2).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_majority_recovers_noisy_responses() {
+        let exact = SimPuf::new(3, 1, 0.0).unwrap();
+        let mut matches = Vec::new();
+        for reads in [1, 5] {
+            let puf = MajorityPuf::new(Arc::new(SimPuf::new(3, 1, 0.02).unwrap()), reads).unwrap();
+            let mut n = 0;
+            for i in 0..200u32 {
+                let c = hex::encode([&i.to_be_bytes()[..], &[0u8; 8]].concat());
+                n += (puf.calculate(&c).await.unwrap() == exact.calculate(&c).await.unwrap()) as usize;
+            }
+            matches.push(n);
+        }
+        // a response of 96 bits at 2% noise is exact with (0.98)^96 = 14% for a single read
+        // and 99% after a vote over five
+        assert!(matches[0] < 80 && matches[1] > 180, "{matches:?}");
+    }
+
+    #[tokio::test]
+    async fn test_majority_follows_inner_geometry() {
+        let geometry = PufGeometry {
+            challenge_bits: 128,
+            response_bits: 256,
+            encoding: crate::PufEncoding::HexLower,
+        };
+        let inner = Arc::new(SimPuf::new(3, 1, 0.0).unwrap().with_geometry(geometry).unwrap());
+        let puf = MajorityPuf::new(inner.clone(), 3).unwrap();
+        let c = "00112233445566778899aabbccddeeff";
+        assert_eq!(puf.geometry(), geometry);
+        assert_eq!(puf.calculate(c).await.unwrap(), inner.calculate(c).await.unwrap());
+        assert_eq!(puf.reliability().unwrap().flip_rate, 0.0);
+    }
+}
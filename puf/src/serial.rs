@@ -0,0 +1,436 @@
+use crate::{
+    DEFAULT_BAUD_RATE, PufBackend, PufGeometry,
+    frame::{CMD_EVAL, CMD_INFO, Frame, STATUS_BAD_COMMAND, STATUS_BAD_CRC, STATUS_BUSY, STATUS_OK, status_name},
+};
+use anyhow::{Context, Result, bail};
+use std::{str::FromStr, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Mutex,
+    time::timeout,
+};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// Quiet time on the line after which a raw link counts as drained.
+const DRAIN_QUIET: Duration = Duration::from_millis(50);
+
+/// Wire protocol spoken by the board.
+///
+/// Raw by default, since that is what the `puf_uart` bitstream in this repository speaks; a board
+/// with the frame protocol has to be selected with `protocol=framed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialProtocol {
+    /// Frames with sync byte, length, sequence number, status and CRC; see `frame.rs`.
+    Framed,
+    /// Bare challenges and responses, as the original `puf_uart` bitstream speaks.
+    Raw,
+}
+
+impl FromStr for SerialProtocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "framed" => Ok(Self::Framed),
+            "raw" => Ok(Self::Raw),
+            _ => bail!("Unknown serial PUF protocol: {s} (expected framed or raw)"),
+        }
+    }
+}
+
+/// Line settings and error recovery of a serial PUF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud: u32,
+    pub protocol: SerialProtocol,
+    /// Time the board gets to answer one request.
+    pub timeout: Duration,
+    /// Requests sent again after a timeout, a corrupted reply or a busy board.
+    pub retries: u32,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud: DEFAULT_BAUD_RATE,
+            protocol: SerialProtocol::Raw,
+            timeout: Duration::from_millis(500),
+            retries: 3,
+        }
+    }
+}
+
+/// PUF client that communicates over a serial port.
+/// Each request carries a challenge and its reply the response, both in the widths of `geometry`.
+pub struct SerialPuf {
+    /// Serial link, locked for a whole request/response cycle so that cycles are not interleaved.
+    link: Mutex<Link>,
+    config: SerialConfig,
+    geometry: PufGeometry,
+}
+
+struct Link {
+    port: SerialStream,
+    /// Received bytes not consumed yet.
+    buf: Vec<u8>,
+    /// Sequence number of the last framed request.
+    seq: u8,
+}
+
+impl SerialPuf {
+    /// Create a new PUF client using a serial port.
+    ///
+    /// A framed board is asked for its widths; `geometry` holds for boards that cannot tell and
+    /// always gives the response encoding.
+    ///
+    /// # Arguments
+    /// * `port_name` - Serial port device path (e.g., "/dev/ttyUSB0" or "COM3")
+    /// * `config` - Baud rate, protocol, timeout and retries
+    /// * `geometry` - Challenge and response widths and the response encoding
+    pub async fn connect(port_name: &str, config: SerialConfig, geometry: PufGeometry) -> Result<Self> {
+        // Open serial port in async mode
+        let serial = tokio_serial::new(port_name, config.baud)
+            .open_native_async()
+            .context("failed to open serial port")?;
+        Self::start(serial, config, geometry).await
+    }
+
+    /// Set up the link; only a framed board is asked for its widths, a raw one would not answer.
+    async fn start(port: SerialStream, config: SerialConfig, geometry: PufGeometry) -> Result<Self> {
+        let mut puf = Self::with_port(port, config, geometry);
+        if puf.config.protocol == SerialProtocol::Framed {
+            puf.negotiate().await?;
+        }
+        Ok(puf)
+    }
+
+    fn with_port(port: SerialStream, config: SerialConfig, geometry: PufGeometry) -> Self {
+        let link = Link {
+            port,
+            buf: Vec::new(),
+            seq: 0,
+        };
+        Self {
+            link: Mutex::new(link),
+            config,
+            geometry,
+        }
+    }
+
+    /// Take the widths the board reports; a board without [`CMD_INFO`] keeps the configured ones.
+    async fn negotiate(&mut self) -> Result<()> {
+        let reply = self.transact(CMD_INFO, &[]).await?;
+        match (reply.code, &reply.payload[..]) {
+            (STATUS_OK, &[c0, c1, r0, r1]) => {
+                let geometry = PufGeometry {
+                    challenge_bits: u16::from_be_bytes([c0, c1]) as usize,
+                    response_bits: u16::from_be_bytes([r0, r1]) as usize,
+                    encoding: self.geometry.encoding,
+                };
+                geometry.validate().context("board reported an unusable geometry")?;
+                self.geometry = geometry;
+            }
+            (STATUS_OK, _) => bail!("board answered the info request with {} bytes", reply.payload.len()),
+            (STATUS_BAD_COMMAND, _) => {}
+            (status, _) => bail!("board rejected the info request: {}", status_name(status)),
+        }
+        Ok(())
+    }
+
+    /// Send a challenge and return the response, both in the widths of the geometry.
+    ///
+    /// Timeouts, replies that fail the CRC and a busy board lead to a new request, up to
+    /// `config.retries` times; I/O errors and requests the board rejects fail right away.
+    pub async fn evaluate(&self, challenge: &[u8]) -> Result<Vec<u8>> {
+        if challenge.len() != self.geometry.challenge_bytes() {
+            bail!(
+                "challenge must be exactly {} bytes, got {}",
+                self.geometry.challenge_bytes(),
+                challenge.len()
+            );
+        }
+
+        let reply = self.transact(CMD_EVAL, challenge).await?;
+        match reply.code {
+            STATUS_OK if reply.payload.len() == self.geometry.response_bytes() => Ok(reply.payload),
+            STATUS_OK => bail!("board answered with {} response bytes", reply.payload.len()),
+            status => bail!("board rejected the challenge: {}", status_name(status)),
+        }
+    }
+
+    /// One request with retries; the reply carries any status but bad CRC and busy.
+    async fn transact(&self, code: u8, payload: &[u8]) -> Result<Frame> {
+        let mut link = self.link.lock().await;
+        let mut failure = String::new();
+        for _ in 0..=self.config.retries {
+            let exchange = link.exchange(self.config.protocol, code, payload, self.geometry.response_bytes());
+            failure = match timeout(self.config.timeout, exchange).await {
+                Ok(Ok(Ok(reply))) => return Ok(reply),
+                Ok(Ok(Err(status))) => format!("board answered {}", status_name(status)),
+                Ok(Err(e)) => return Err(e),
+                Err(_) => format!("no reply within {:?}", self.config.timeout),
+            };
+            if self.config.protocol == SerialProtocol::Raw {
+                // without framing, the rest of a late reply would shift every later response
+                link.drain().await;
+            }
+        }
+        bail!("serial PUF failed after {} attempts: {}", self.config.retries + 1, failure)
+    }
+}
+
+impl Link {
+    /// One request/response cycle; `Err(status)` for a status worth another request.
+    ///
+    /// A raw link only evaluates challenges, and its reply is the next `raw_reply_len` bytes.
+    async fn exchange(&mut self, protocol: SerialProtocol, code: u8, payload: &[u8], raw_reply_len: usize) -> Result<Result<Frame, u8>> {
+        self.buf.clear();
+        if protocol == SerialProtocol::Raw {
+            if code != CMD_EVAL {
+                return Ok(Ok(Frame {
+                    seq: 0,
+                    code: STATUS_BAD_COMMAND,
+                    payload: vec![],
+                }));
+            }
+            self.port.write_all(payload).await?;
+            self.port.flush().await?;
+            while self.buf.len() < raw_reply_len {
+                self.read_more().await?;
+            }
+            return Ok(Ok(Frame {
+                seq: 0,
+                code: STATUS_OK,
+                payload: self.buf.drain(..raw_reply_len).collect(),
+            }));
+        }
+
+        self.seq = self.seq.wrapping_add(1);
+        let request = Frame {
+            seq: self.seq,
+            code,
+            payload: payload.to_vec(),
+        };
+        self.port.write_all(&request.encode()).await?;
+        self.port.flush().await?;
+        loop {
+            while let Some(reply) = Frame::decode(&mut self.buf) {
+                if reply.seq != self.seq {
+                    // late reply to a request that already timed out
+                    continue;
+                }
+                return match reply.code {
+                    STATUS_BAD_CRC | STATUS_BUSY => Ok(Err(reply.code)),
+                    _ => Ok(Ok(reply)),
+                };
+            }
+            self.read_more().await?;
+        }
+    }
+
+    async fn read_more(&mut self) -> Result<()> {
+        let mut chunk = [0u8; 64];
+        let n = self.port.read(&mut chunk).await?;
+        if n == 0 {
+            bail!("serial port closed");
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(())
+    }
+
+    /// Discard whatever the board still sends for an abandoned request.
+    async fn drain(&mut self) {
+        while let Ok(Ok(())) = timeout(DRAIN_QUIET, self.read_more()).await {}
+        self.buf.clear();
+    }
+}
+
+#[async_trait::async_trait]
+impl PufBackend for SerialPuf {
+    async fn calculate(&self, challenge: &str) -> Result<String> {
+        let response = self.evaluate(&self.geometry.decode_challenge(challenge)?).await?;
+        Ok(self.geometry.encode_response(&response))
+    }
+
+    fn geometry(&self) -> PufGeometry {
+        self.geometry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{STATUS_BAD_LENGTH, SYNC};
+
+    /// Device id the emulated board XORs into every challenge, like `main.v` does with `id_o`.
+    const BOARD_ID: [u8; 12] = *b"puf_uart-id!";
+
+    enum Fault {
+        /// Send line noise ahead of the reply.
+        Noise,
+        /// Swallow the request.
+        Drop,
+        /// Flip a bit of the reply.
+        Corrupt,
+        /// Answer busy.
+        Busy,
+        /// Answer after the client gave up on the request.
+        Late,
+        /// Send only the first bytes of the reply.
+        Truncate,
+    }
+
+    fn expected(challenge: &[u8]) -> Vec<u8> {
+        challenge.iter().zip(BOARD_ID.iter().cycle()).map(|(c, id)| c ^ id).collect()
+    }
+
+    /// Stand-in for the `puf_uart` board on the other end of a pseudo-terminal.
+    ///
+    /// Every request is answered with `BOARD_ID ^ challenge` after the next fault in `faults`, if
+    /// any, has been applied to it. A board with `width` bytes answers [`CMD_INFO`] with it; one
+    /// without takes 12-byte challenges and does not know the command, like the original bitstream.
+    async fn board(mut port: SerialStream, protocol: SerialProtocol, width: Option<usize>, mut faults: Vec<Option<Fault>>) {
+        let block = width.unwrap_or(BOARD_ID.len());
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 64];
+        faults.reverse();
+        loop {
+            let request = match protocol {
+                SerialProtocol::Framed => Frame::decode(&mut buf),
+                SerialProtocol::Raw if buf.len() >= block => Some(Frame {
+                    seq: 0,
+                    code: CMD_EVAL,
+                    payload: buf.drain(..block).collect(),
+                }),
+                SerialProtocol::Raw => None,
+            };
+            let Some(request) = request else {
+                match port.read(&mut chunk).await {
+                    Ok(n) if n > 0 => buf.extend_from_slice(&chunk[..n]),
+                    _ => return,
+                }
+                continue;
+            };
+
+            let (code, payload) = match (request.code, request.payload.len()) {
+                (CMD_EVAL, len) if len == block => (STATUS_OK, expected(&request.payload)),
+                (CMD_EVAL, _) => (STATUS_BAD_LENGTH, vec![]),
+                (CMD_INFO, 0) if width.is_some() => (STATUS_OK, [(block as u16 * 8).to_be_bytes(); 2].concat()),
+                _ => (STATUS_BAD_COMMAND, vec![]),
+            };
+            let fault = faults.pop().flatten();
+            let code = if let Some(Fault::Busy) = fault { STATUS_BUSY } else { code };
+            let mut reply = match protocol {
+                SerialProtocol::Framed => Frame {
+                    seq: request.seq,
+                    code,
+                    payload,
+                }
+                .encode(),
+                SerialProtocol::Raw => payload,
+            };
+            match fault {
+                Some(Fault::Noise) => reply.splice(0..0, [0x00, SYNC, 0x05, SYNC, 0xFF]).for_each(drop),
+                Some(Fault::Drop) => continue,
+                Some(Fault::Corrupt) => reply[6] ^= 0x01,
+                Some(Fault::Late) => tokio::time::sleep(Duration::from_millis(300)).await,
+                Some(Fault::Truncate) => reply.truncate(5),
+                Some(Fault::Busy) | None => {}
+            }
+            if port.write_all(&reply).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn connect(protocol: SerialProtocol, faults: Vec<Option<Fault>>) -> SerialPuf {
+        connect_board(protocol, None, faults)
+    }
+
+    fn connect_board(protocol: SerialProtocol, width: Option<usize>, faults: Vec<Option<Fault>>) -> SerialPuf {
+        let (client, board_end) = SerialStream::pair().unwrap();
+        tokio::spawn(board(board_end, protocol, width, faults));
+        let config = SerialConfig {
+            protocol,
+            timeout: Duration::from_millis(200),
+            ..SerialConfig::default()
+        };
+        SerialPuf::with_port(client, config, PufGeometry::default())
+    }
+
+    #[tokio::test]
+    async fn test_framed_recovers_from_line_faults() {
+        let faults = vec![
+            Some(Fault::Noise),
+            Some(Fault::Drop),
+            Some(Fault::Corrupt),
+            Some(Fault::Busy),
+            None,
+            Some(Fault::Drop),
+            Some(Fault::Drop),
+            Some(Fault::Drop),
+            Some(Fault::Late),
+        ];
+        let puf = connect(SerialProtocol::Framed, faults);
+        let (challenge, other) = (*b"challenge-01", *b"challenge-02");
+        assert_eq!(puf.evaluate(&challenge).await.unwrap(), expected(&challenge));
+        // dropped, corrupted and busy before the fourth request gets through
+        assert_eq!(puf.evaluate(&challenge).await.unwrap(), expected(&challenge));
+        // the reply to the last attempt comes too late and arrives while waiting for the next one
+        assert!(puf.evaluate(&challenge).await.is_err());
+        assert_eq!(puf.evaluate(&other).await.unwrap(), expected(&other));
+        assert_eq!(
+            puf.calculate(&hex::encode(challenge)).await.unwrap(),
+            hex::encode_upper(expected(&challenge))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_raw_resyncs_after_truncated_reply() {
+        let puf = connect(SerialProtocol::Raw, vec![Some(Fault::Truncate)]);
+        let challenge = *b"challenge-03";
+        assert_eq!(puf.evaluate(&challenge).await.unwrap(), expected(&challenge));
+        assert_eq!(puf.evaluate(&challenge).await.unwrap(), expected(&challenge));
+    }
+
+    #[tokio::test]
+    async fn test_default_config_talks_to_raw_bitstream() {
+        // a raw board never answers the info request, so startup must not send it
+        let (client, board_end) = SerialStream::pair().unwrap();
+        tokio::spawn(board(board_end, SerialProtocol::Raw, None, vec![]));
+        let config = SerialConfig {
+            timeout: Duration::from_millis(200),
+            ..SerialConfig::default()
+        };
+        let puf = SerialPuf::start(client, config, PufGeometry::default()).await.unwrap();
+        assert_eq!(puf.evaluate(b"challenge-01").await.unwrap(), expected(b"challenge-01"));
+    }
+
+    #[tokio::test]
+    async fn test_negotiates_board_geometry() {
+        let mut puf = connect_board(SerialProtocol::Framed, Some(32), vec![Some(Fault::Busy)]);
+        puf.negotiate().await.unwrap();
+        assert_eq!((puf.geometry().challenge_bits, puf.geometry().response_bits), (256, 256));
+        let challenge = [0x5Au8; 32];
+        assert_eq!(puf.evaluate(&challenge).await.unwrap(), expected(&challenge));
+        assert!(puf.evaluate(b"challenge-01").await.is_err());
+
+        // a framed board reporting its widths is only asked when framing was selected
+        let (client, board_end) = SerialStream::pair().unwrap();
+        tokio::spawn(board(board_end, SerialProtocol::Framed, Some(32), vec![]));
+        let config = SerialConfig {
+            protocol: SerialProtocol::Framed,
+            ..SerialConfig::default()
+        };
+        let puf = SerialPuf::start(client, config, PufGeometry::default()).await.unwrap();
+        assert_eq!(puf.geometry().challenge_bits, 256);
+
+        // the original bitstream does not know the info command and keeps the configured widths
+        for protocol in [SerialProtocol::Framed, SerialProtocol::Raw] {
+            let mut puf = connect_board(protocol, None, vec![]);
+            puf.negotiate().await.unwrap();
+            assert_eq!(puf.geometry(), PufGeometry::default());
+            assert_eq!(puf.evaluate(b"challenge-01").await.unwrap(), expected(b"challenge-01"));
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{PufBackend, PufGeometry};
+use puf_sim::XorArbiterPuf;
+
+/// Bits of a sub-challenge, as in `puf_py`.
+const SUB_CHALLENGE_BITS: usize = 8;
+
+/// In-process PUF: the XOR arbiter model of `puf_sim`, so a seed answers like a `puf_sim` server
+/// started with the same seed and widths.
+pub struct SimPuf {
+    puf: XorArbiterPuf,
+    geometry: PufGeometry,
+}
+
+impl SimPuf {
+    pub fn new(seed: u64, k: usize, noise: f64) -> anyhow::Result<Self> {
+        Ok(Self {
+            puf: XorArbiterPuf::new(SUB_CHALLENGE_BITS, k, seed, noise)?,
+            geometry: PufGeometry::default(),
+        })
+    }
+
+    pub fn with_geometry(self, geometry: PufGeometry) -> anyhow::Result<Self> {
+        geometry.validate()?;
+        Ok(Self {
+            puf: self.puf.with_widths(geometry.challenge_bits, geometry.response_bits)?,
+            geometry,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl PufBackend for SimPuf {
+    async fn calculate(&self, challenge: &str) -> anyhow::Result<String> {
+        Ok(self.geometry.encode_response(&self.puf.response(challenge)?))
+    }
+
+    fn geometry(&self) -> PufGeometry {
+        self.geometry
+    }
+}
@@ -0,0 +1,430 @@
+use crate::{PufBackend, PufGeometry};
+use anyhow::{Context, bail};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::Notify,
+    time::timeout,
+};
+
+/// First pause before another attempt at a failed request, doubled after every failure.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Sizing, timeouts and retries of a [`TcpPuf`] pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpPoolConfig {
+    /// Connections opened up front, so that an unreachable server shows at startup.
+    pub initial_size: usize,
+    /// Upper bound of open connections; further concurrent requests wait for a free one.
+    pub max_size: usize,
+    pub connect_timeout: Duration,
+    /// Time one attempt at a request may take, waiting for a connection included.
+    pub request_timeout: Duration,
+    /// Attempts after the first, each on a healthy or new connection.
+    pub retries: u32,
+}
+
+impl Default for TcpPoolConfig {
+    fn default() -> Self {
+        Self {
+            initial_size: 1,
+            max_size: 1 << 3,
+            connect_timeout: Duration::from_secs(1),
+            request_timeout: Duration::from_secs(2),
+            retries: 5,
+        }
+    }
+}
+
+/// Counters of a [`TcpPuf`] pool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    pub requests: u64,
+    /// Failed attempts, timeouts included; a request that succeeds on a retry counts its failures.
+    pub failed_attempts: u64,
+    pub timeouts: u64,
+    /// Connections opened, the initial ones included.
+    pub connects: u64,
+    /// Connections dropped as broken, desynchronized or closed by the server.
+    pub discarded: u64,
+    pub open: usize,
+    pub idle: usize,
+}
+
+impl fmt::Display for PoolMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requests, {} failed attempts ({} timeouts), {} connects, {} discarded, {} open, {} idle",
+            self.requests, self.failed_attempts, self.timeouts, self.connects, self.discarded, self.open, self.idle
+        )
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    failed_attempts: AtomicU64,
+    timeouts: AtomicU64,
+    connects: AtomicU64,
+    discarded: AtomicU64,
+}
+
+struct PoolState {
+    idle: VecDeque<TcpStream>,
+    /// Idle connections plus those leased out or being opened.
+    open: usize,
+}
+
+/// Puf client backed by a TCP connection pool for concurrent hex request/response exchanges.
+///
+/// The pool grows on demand up to `max_size`. A connection goes back to the pool only after a
+/// complete exchange; one that failed or timed out mid-exchange is closed, and idle connections
+/// are checked before reuse, so a restarted server just leads to new connections.
+pub struct TcpPuf {
+    addr: String,
+    config: TcpPoolConfig,
+    /// The server protocol has no way to report it, so it comes from the configuration.
+    geometry: PufGeometry,
+    state: Mutex<PoolState>,
+    notify: Notify,
+    counters: Counters,
+}
+
+/// A pool slot held by one request; gives the slot up on drop unless the connection was released.
+struct Lease<'a> {
+    pool: &'a TcpPuf,
+    stream: Option<TcpStream>,
+    released: bool,
+}
+
+impl TcpPuf {
+    /// Create a new client with the default pool configuration.
+    pub async fn new(addr: &str) -> anyhow::Result<Self> {
+        Self::with_config(addr, TcpPoolConfig::default()).await
+    }
+
+    /// Create a new client whose pool grows up to `pool_size` connections.
+    pub async fn new_with_pool_size(addr: &str, pool_size: usize) -> anyhow::Result<Self> {
+        let config = TcpPoolConfig {
+            max_size: pool_size,
+            ..TcpPoolConfig::default()
+        };
+        Self::with_config(addr, config).await
+    }
+
+    /// Create a new client and immediately open `config.initial_size` connections.
+    pub async fn with_config(addr: &str, config: TcpPoolConfig) -> anyhow::Result<Self> {
+        if config.max_size == 0 {
+            bail!("pool size must be greater than 0");
+        }
+
+        let puf = Self {
+            addr: addr.to_string(),
+            geometry: PufGeometry::default(),
+            state: Mutex::new(PoolState {
+                idle: VecDeque::with_capacity(config.max_size),
+                open: 0,
+            }),
+            config,
+            notify: Notify::new(),
+            counters: Counters::default(),
+        };
+        for _ in 0..puf.config.initial_size.min(puf.config.max_size) {
+            let stream = puf.connect().await?;
+            let mut state = puf.state.lock().unwrap();
+            state.idle.push_back(stream);
+            state.open += 1;
+        }
+        Ok(puf)
+    }
+
+    /// Expect the server to answer in `geometry` instead of the default 96-bit widths.
+    pub fn with_geometry(self, geometry: PufGeometry) -> anyhow::Result<Self> {
+        geometry.validate()?;
+        Ok(Self { geometry, ..self })
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        let state = self.state.lock().unwrap();
+        PoolMetrics {
+            requests: self.counters.requests.load(Ordering::Relaxed),
+            failed_attempts: self.counters.failed_attempts.load(Ordering::Relaxed),
+            timeouts: self.counters.timeouts.load(Ordering::Relaxed),
+            connects: self.counters.connects.load(Ordering::Relaxed),
+            discarded: self.counters.discarded.load(Ordering::Relaxed),
+            open: state.open,
+            idle: state.idle.len(),
+        }
+    }
+
+    async fn connect(&self) -> anyhow::Result<TcpStream> {
+        let stream = timeout(self.config.connect_timeout, TcpStream::connect(&self.addr))
+            .await
+            .context("timed out connecting to PUF server")?
+            .context("failed to connect to PUF server")?;
+        stream.set_nodelay(true)?;
+        self.counters.connects.fetch_add(1, Ordering::Relaxed);
+        Ok(stream)
+    }
+
+    /// A healthy idle connection, a new one while the pool is below `max_size`, or else the next
+    /// connection released.
+    async fn acquire(&self) -> anyhow::Result<Lease<'_>> {
+        loop {
+            let notified = self.notify.notified();
+            let grow = {
+                let mut state = self.state.lock().unwrap();
+                let mut healthy = None;
+                while let Some(stream) = state.idle.pop_front() {
+                    if is_healthy(&stream) {
+                        healthy = Some(stream);
+                        break;
+                    }
+                    state.open -= 1;
+                    self.counters.discarded.fetch_add(1, Ordering::Relaxed);
+                }
+                if let Some(stream) = healthy {
+                    return Ok(self.lease(Some(stream)));
+                }
+                let grow = state.open < self.config.max_size;
+                if grow {
+                    state.open += 1;
+                }
+                grow
+            };
+            if grow {
+                // the lease owns the slot from here on, so a failed connect gives it back
+                let mut lease = self.lease(None);
+                lease.stream = Some(self.connect().await?);
+                return Ok(lease);
+            }
+            notified.await;
+        }
+    }
+
+    fn lease(&self, stream: Option<TcpStream>) -> Lease<'_> {
+        Lease {
+            pool: self,
+            stream,
+            released: false,
+        }
+    }
+
+    /// One attempt at a request on a pooled connection.
+    async fn exchange(&self, payload: &[u8]) -> anyhow::Result<String> {
+        let mut lease = self.acquire().await?;
+        let stream = lease.stream.as_mut().expect("leased connection");
+        stream.write_all(payload).await?;
+        stream.flush().await?;
+
+        let mut resp_buf = vec![0u8; self.geometry.response_bytes() * 2];
+        stream.read_exact(&mut resp_buf).await?;
+        let response = String::from_utf8(resp_buf).context("PUF server sent a malformed response")?;
+        self.geometry
+            .decode_response(&response)
+            .context("PUF server sent a malformed response")?;
+
+        lease.release();
+        Ok(response)
+    }
+}
+
+impl Lease<'_> {
+    fn release(&mut self) {
+        let stream = self.stream.take().expect("leased connection");
+        self.pool.state.lock().unwrap().idle.push_back(stream);
+        self.released = true;
+        self.pool.notify.notify_one();
+    }
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if self.stream.is_some() {
+            self.pool.counters.discarded.fetch_add(1, Ordering::Relaxed);
+        }
+        self.pool.state.lock().unwrap().open -= 1;
+        self.pool.notify.notify_one();
+    }
+}
+
+/// An idle connection is healthy while nothing can be read from it: end of stream means the
+/// server closed it, and stray bytes mean it is out of step with the requests.
+fn is_healthy(stream: &TcpStream) -> bool {
+    matches!(stream.try_read(&mut [0u8; 1]), Err(e) if e.kind() == std::io::ErrorKind::WouldBlock)
+}
+
+#[async_trait::async_trait]
+impl PufBackend for TcpPuf {
+    /// Send a hex challenge and read the hex response using a pooled connection.
+    ///
+    /// Attempts that fail or exceed `request_timeout` are repeated with backoff, up to `retries` times.
+    async fn calculate(&self, challenge: &str) -> anyhow::Result<String> {
+        self.geometry.decode_challenge(challenge)?;
+        let payload = challenge.as_bytes();
+
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+        let mut backoff = RETRY_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let error = match timeout(self.config.request_timeout, self.exchange(payload)).await {
+                Ok(Ok(response)) => return Ok(response),
+                Ok(Err(e)) => e,
+                Err(_) => {
+                    self.counters.timeouts.fetch_add(1, Ordering::Relaxed);
+                    anyhow::anyhow!("no PUF response within {:?}", self.config.request_timeout)
+                }
+            };
+            self.counters.failed_attempts.fetch_add(1, Ordering::Relaxed);
+            if attempt == self.config.retries {
+                return Err(error.context(format!("PUF request failed after {} attempts", attempt + 1)));
+            }
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    fn geometry(&self) -> PufGeometry {
+        self.geometry
+    }
+
+    fn pool_metrics(&self) -> Option<PoolMetrics> {
+        Some(self.metrics())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{net::TcpListener, task::JoinSet};
+
+    const CHALLENGE: &str = "00112233445566778899AABB";
+
+    /// Answer every challenge of `digits` hex digits with its reverse after `delay`, followed by `trailer`.
+    async fn serve(listener: TcpListener, digits: usize, delay: Duration, trailer: &'static [u8]) {
+        // dropped with the server task, which closes every connection like a restart would
+        let mut conns = JoinSet::new();
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            conns.spawn(async move {
+                let mut buf = vec![0u8; digits];
+                while stream.read_exact(&mut buf).await.is_ok() {
+                    tokio::time::sleep(delay).await;
+                    buf.reverse();
+                    if stream.write_all(&[&buf[..], trailer].concat()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+
+    async fn start(delay: Duration, trailer: &'static [u8]) -> (String, tokio::task::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        (addr, tokio::spawn(serve(listener, CHALLENGE.len(), delay, trailer)))
+    }
+
+    fn config(max_size: usize) -> TcpPoolConfig {
+        TcpPoolConfig {
+            max_size,
+            request_timeout: Duration::from_millis(300),
+            retries: 3,
+            ..TcpPoolConfig::default()
+        }
+    }
+
+    fn expected() -> String {
+        CHALLENGE.chars().rev().collect()
+    }
+
+    #[tokio::test]
+    async fn test_pool_survives_server_restart() {
+        let (addr, server) = start(Duration::ZERO, b"").await;
+        let puf = TcpPuf::with_config(&addr, config(2)).await.unwrap();
+        assert_eq!(puf.calculate(CHALLENGE).await.unwrap(), expected());
+
+        server.abort();
+        let _ = server.await;
+        let listener = TcpListener::bind(&addr).await.unwrap();
+        tokio::spawn(serve(listener, CHALLENGE.len(), Duration::ZERO, b""));
+
+        assert_eq!(puf.calculate(CHALLENGE).await.unwrap(), expected());
+        let metrics = puf.metrics();
+        assert_eq!((metrics.connects, metrics.discarded, metrics.open), (2, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_pool_discards_desynchronized_connections() {
+        // a trailing byte after each response leaves the connection out of step
+        let (addr, _server) = start(Duration::ZERO, b"X").await;
+        let puf = TcpPuf::with_config(&addr, config(1)).await.unwrap();
+        for _ in 0..3 {
+            assert_eq!(puf.calculate(CHALLENGE).await.unwrap(), expected());
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(puf.metrics().discarded, 2);
+    }
+
+    #[tokio::test]
+    async fn test_pool_grows_lazily_and_times_out() {
+        let (addr, _server) = start(Duration::from_millis(100), b"").await;
+        let puf = TcpPuf::with_config(&addr, config(3)).await.unwrap();
+        assert_eq!(puf.metrics().open, 1);
+        let puf = std::sync::Arc::new(puf);
+        let mut requests = JoinSet::new();
+        for _ in 0..6 {
+            let puf = puf.clone();
+            requests.spawn(async move { puf.calculate(CHALLENGE).await });
+        }
+        while let Some(response) = requests.join_next().await {
+            assert_eq!(response.unwrap().unwrap(), expected());
+        }
+        assert_eq!((puf.metrics().connects, puf.metrics().open), (3, 3));
+
+        let (addr, _server) = start(Duration::from_secs(3600), b"").await;
+        let puf = TcpPuf::with_config(&addr, config(1)).await.unwrap();
+        assert!(puf.calculate(CHALLENGE).await.is_err());
+        let metrics = puf.metrics();
+        assert_eq!((metrics.timeouts, metrics.failed_attempts), (4, 4));
+    }
+
+    #[tokio::test]
+    async fn test_geometry_sets_wire_widths() {
+        let geometry = PufGeometry {
+            challenge_bits: 128,
+            response_bits: 128,
+            encoding: crate::PufEncoding::HexLower,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(listener, 32, Duration::ZERO, b""));
+        let puf = TcpPuf::with_config(&addr, config(1))
+            .await
+            .unwrap()
+            .with_geometry(geometry)
+            .unwrap();
+
+        let challenge = "00112233445566778899aabbccddeeff";
+        assert_eq!(puf.calculate(challenge).await.unwrap(), challenge.chars().rev().collect::<String>());
+        // rejected before it reaches the server
+        assert!(puf.calculate(CHALLENGE).await.is_err());
+        // an upper-case answer does not match the recorded encoding
+        assert!(puf.calculate(&challenge.to_uppercase()).await.is_err());
+        assert_eq!(puf.metrics().requests, 2);
+    }
+}
@@ -1,3 +1,4 @@
+import argparse
 import socket
 import numpy as np
 import hashlib
@@ -10,6 +11,10 @@ from pypuf.simulation import XORArbiterPUF
 n = 8
 puf = XORArbiterPUF(n=n, k=1, seed=1)
 
+# Challenge and response widths in bits, set from the command line
+challenge_bits = 96
+response_bits = 96
+
 def hex_string_to_ndarray(hex_string: str) -> np.ndarray:
     """
     Convert a hex string into an ndarray of shape (rows, n),
@@ -37,30 +42,30 @@ def ndarray_to_hex_string(ndarray: np.ndarray) -> str:
     hexstr = hex(int(binary_string, 2))[2:].upper().zfill(hex_len)
     return hexstr
 
-def expand_hex_string_to_ndarray(hex_string: str) -> np.ndarray:
+def expand_hex_string_to_ndarray(hex_string: str, rows: int) -> np.ndarray:
     """
-    Expand the initial 12-byte challenge into 96 total sub-challenges:
-    start with shape (12,8), then hash 7 times to append 7×(12,8),
-    yielding a final (96,8) matrix.
+    Expand the challenge into `rows` sub-challenges, one per response bit:
+    start with the challenge bits, then append the bits of SHA-256 chained
+    over the challenge and the previous digests until there are enough.
     """
     # First block
-    c = hex_string_to_ndarray(hex_string)  # shape (12,8)
+    c = hex_string_to_ndarray(hex_string)  # 96-bit challenge: shape (12,8)
     m = hashlib.sha256()
-    for _ in range(7):
+    while c.shape[0] < rows:
         m.update(hex_string.encode('utf-8'))
         hex_string = m.hexdigest()
-        nd = hex_string_to_ndarray(hex_string)  # also (12,8)
+        nd = hex_string_to_ndarray(hex_string)  # (32,8)
         c = np.concatenate((c, nd), axis=0)
-    return c  # final shape (96,8)
+    return c[:rows]  # shape (rows,8)
 
 def get_puf(hex_challenge: str) -> str:
     """
-    Compute the PUF response for a given 12-byte hex challenge.
-    Returns a 12-byte hex response.
+    Compute the PUF response for a hex challenge of `challenge_bits`.
+    Returns a hex response of `response_bits`.
     """
-    c = expand_hex_string_to_ndarray(hex_challenge)  # (96,8)
-    r = puf.eval(c)                                   # 96-bit response
-    return ndarray_to_hex_string(r)[:24]                   # 24 hex chars
+    c = expand_hex_string_to_ndarray(hex_challenge, response_bits)
+    r = puf.eval(c)
+    return ndarray_to_hex_string(r)
 
 # --- TCP server: listen on port 12345 ---
 def recv_exact(sock, n):
@@ -110,8 +115,8 @@ def handle_client(conn, addr):
         conn.settimeout(10)
         try:
             while True:
-                # read exactly 24 hex chars challenge
-                buf = recv_exact(conn, 24)
+                # read exactly one hex challenge
+                buf = recv_exact(conn, challenge_bits // 4)
                 if buf is None:
                     logging.info("Client %s closed the connection", addr)
                     return
@@ -119,8 +124,8 @@ def handle_client(conn, addr):
                 hex_in = buf.decode("utf-8", errors="strict").strip()
                 logging.info("Received from %s: %r", addr, hex_in)
 
-                if len(hex_in) != 24:
-                    logging.warning("Invalid length from %s: %d (expected 24)", addr, len(hex_in))
+                if len(hex_in) != challenge_bits // 4:
+                    logging.warning("Invalid length from %s: %d (expected %d)", addr, len(hex_in), challenge_bits // 4)
                     return
                 try:
                     int(hex_in, 16)
@@ -161,4 +166,11 @@ def run_server():
 logging.basicConfig(level=logging.INFO, format="%(asctime)s [%(levelname)s] %(message)s")
 
 if __name__ == "__main__":
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--challenge-bits", type=int, default=challenge_bits, help="bits of a challenge, a multiple of 8")
+    parser.add_argument("--response-bits", type=int, default=response_bits, help="bits of a response, a multiple of 8")
+    args = parser.parse_args()
+    if args.challenge_bits <= 0 or args.challenge_bits % 8 or args.response_bits <= 0 or args.response_bits % 8:
+        parser.error("widths must be positive multiples of 8 bits")
+    challenge_bits, response_bits = args.challenge_bits, args.response_bits
     run_server()
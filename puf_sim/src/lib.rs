@@ -0,0 +1,206 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+/// Bits of a challenge and of a response unless [`XorArbiterPuf::with_widths`] says otherwise.
+pub const DEFAULT_BITS: usize = 96;
+
+/// Simulated k-XOR arbiter PUF.
+///
+/// Every chain is an additive delay model over the parity features of an `n`-bit sub-challenge,
+/// with standard normal stage weights and bias drawn from `seed`; the response bit is the XOR of
+/// the chain outputs. `noise` is the chance that a response bit flips on a single reading.
+#[derive(Debug, Clone)]
+pub struct XorArbiterPuf {
+    n: usize,
+    /// Per chain the `n` stage weights followed by the bias.
+    chains: Vec<Vec<f64>>,
+    noise: f64,
+    challenge_bits: usize,
+    response_bits: usize,
+}
+
+impl XorArbiterPuf {
+    pub fn new(n: usize, k: usize, seed: u64, noise: f64) -> anyhow::Result<Self> {
+        // every expansion block is a multiple of 32 bits
+        if n == 0 || 32 % n != 0 {
+            anyhow::bail!("sub-challenge length must divide 32, got {n}");
+        }
+        if k == 0 {
+            anyhow::bail!("at least one arbiter chain needed");
+        }
+        if !(0.0..=0.5).contains(&noise) {
+            anyhow::bail!("noise rate must be within [0, 0.5], got {noise}");
+        }
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let chains = (0..k).map(|_| (0..=n).map(|_| standard_normal(&mut rng)).collect()).collect();
+        Ok(Self {
+            n,
+            chains,
+            noise,
+            challenge_bits: DEFAULT_BITS,
+            response_bits: DEFAULT_BITS,
+        })
+    }
+
+    /// Answer challenges of `challenge_bits` with responses of `response_bits`, both whole bytes.
+    pub fn with_widths(self, challenge_bits: usize, response_bits: usize) -> anyhow::Result<Self> {
+        if challenge_bits == 0 || !challenge_bits.is_multiple_of(8) || !challenge_bits.is_multiple_of(self.n) {
+            anyhow::bail!(
+                "challenge width must be a multiple of 8 bits and of n={}, got {challenge_bits}",
+                self.n
+            );
+        }
+        if response_bits == 0 || !response_bits.is_multiple_of(8) {
+            anyhow::bail!("response width must be a multiple of 8 bits, got {response_bits}");
+        }
+        Ok(Self {
+            challenge_bits,
+            response_bits,
+            ..self
+        })
+    }
+
+    pub fn challenge_bits(&self) -> usize {
+        self.challenge_bits
+    }
+
+    pub fn response_bits(&self) -> usize {
+        self.response_bits
+    }
+
+    /// Noise-free output of the PUF for one sub-challenge in {-1, +1}.
+    pub fn eval(&self, sub_challenge: &[i8]) -> i8 {
+        self.chains
+            .iter()
+            .map(|w| {
+                let mut feature = 1.0;
+                let mut delay = w[self.n];
+                for i in (0..self.n).rev() {
+                    feature *= sub_challenge[i] as f64;
+                    delay += w[i] * feature;
+                }
+                if delay > 0.0 { 1 } else { -1 }
+            })
+            .product()
+    }
+
+    /// One noisy reading of the response to a challenge given as hex.
+    pub fn response(&self, challenge: &str) -> anyhow::Result<Vec<u8>> {
+        if hex::decode(challenge)?.len() * 8 != self.challenge_bits {
+            anyhow::bail!("challenge must be {} hex digits, got {}", self.challenge_bits / 4, challenge.len());
+        }
+        let mut rng = rand::thread_rng();
+        let mut response = vec![0u8; self.response_bits / 8];
+        for (i, sub) in expand_challenge(challenge, self.n, self.response_bits)?.iter().enumerate() {
+            let bit = (self.eval(sub) == 1) ^ (self.noise > 0.0 && rng.gen_bool(self.noise));
+            response[i / 8] |= (bit as u8) << (7 - i % 8);
+        }
+        Ok(response)
+    }
+
+    /// [`XorArbiterPuf::response`] as upper-case hex, the wire format of the PUF server.
+    pub fn calculate(&self, challenge: &str) -> anyhow::Result<String> {
+        Ok(hex::encode_upper(self.response(challenge)?))
+    }
+}
+
+/// Expand a hex challenge into its first `rows` sub-challenges of `n` bits, one per response bit.
+///
+/// The challenge bits come first, then the bits of SHA-256 chained over the challenge and the
+/// hex digests before it, exactly as `puf_py/main.py` does; bit 1 maps to +1 and bit 0 to -1.
+pub fn expand_challenge(challenge: &str, n: usize, rows: usize) -> anyhow::Result<Vec<Vec<i8>>> {
+    let mut block = hex::decode(challenge)?;
+    if block.is_empty() || !(block.len() * 8).is_multiple_of(n) {
+        anyhow::bail!(
+            "challenge of {} bits does not split into sub-challenges of {n} bits",
+            block.len() * 8
+        );
+    }
+    let mut bits = Vec::with_capacity(rows * n + 256);
+    let mut hasher = Sha256::new();
+    let mut hex_block = challenge.to_string();
+    loop {
+        bits.extend(
+            block
+                .iter()
+                .flat_map(|b| (0..8).rev().map(move |i| if (b >> i) & 1 == 1 { 1 } else { -1 })),
+        );
+        if bits.len() >= rows * n {
+            break;
+        }
+        hasher.update(hex_block.as_bytes());
+        block = hasher.clone().finalize().to_vec();
+        hex_block = hex::encode(&block);
+    }
+    Ok(bits.chunks(n).take(rows).map(<[i8]>::to_vec).collect())
+}
+
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    // Box-Muller; 1 - u keeps the logarithm finite
+    let (u1, u2) = (1.0 - rng.r#gen::<f64>(), rng.r#gen::<f64>());
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_challenge_matches_puf_py() {
+        // bits of the (96, 8) matrix built by expand_hex_string_to_ndarray in puf_py/main.py
+        let expected = hex::decode(concat!(
+            "00112233445566778899aabbc6680d202ae781852bea690650975ae4c753749e56483677c494f96c8bf00abdd2522434",
+            "a7f3aa37f0eda2a1529a61367721c02d4bbacf3a5fe424c71f1f440d599475af7bb3116dcfbc84f4773d11fcc07adb4b"
+        ))
+        .unwrap();
+        let rows = expand_challenge("00112233445566778899aabb", 8, DEFAULT_BITS).unwrap();
+        assert_eq!(rows.len(), DEFAULT_BITS);
+        for (row, byte) in rows.iter().zip(expected) {
+            let packed = row.iter().fold(0u8, |acc, &b| (acc << 1) | (b == 1) as u8);
+            assert_eq!(packed, byte);
+        }
+        assert!(expand_challenge("0011", 32, 16).is_err());
+    }
+
+    #[test]
+    fn test_devices_differ_per_seed() {
+        let c = "00112233445566778899aabb";
+        let a = XorArbiterPuf::new(8, 4, 1, 0.0).unwrap();
+        let b = XorArbiterPuf::new(8, 4, 2, 0.0).unwrap();
+        let r = a.calculate(c).unwrap();
+        assert_eq!(r.len(), DEFAULT_BITS / 4);
+        assert_eq!(r, a.calculate(c).unwrap());
+        assert_eq!(r, XorArbiterPuf::new(8, 4, 1, 0.0).unwrap().calculate(c).unwrap());
+        assert_ne!(r, b.calculate(c).unwrap());
+        assert!(XorArbiterPuf::new(3, 1, 1, 0.0).is_err());
+        assert!(a.calculate("0011").is_err());
+    }
+
+    #[test]
+    fn test_widths() {
+        let a = XorArbiterPuf::new(8, 2, 1, 0.0).unwrap();
+        let wide = a.clone().with_widths(64, 256).unwrap();
+        let c = "0011223344556677";
+        assert_eq!(wide.calculate(c).unwrap().len(), 64);
+        assert!(wide.calculate("00112233445566778899aabb").is_err());
+        // the leading response bits do not depend on how many follow
+        let narrow = a.with_widths(64, 96).unwrap();
+        assert!(wide.calculate(c).unwrap().starts_with(&narrow.calculate(c).unwrap()));
+        assert!(XorArbiterPuf::new(32, 1, 1, 0.0).unwrap().with_widths(48, 96).is_err());
+    }
+
+    #[test]
+    fn test_noise_rate() {
+        let exact = XorArbiterPuf::new(8, 1, 7, 0.0).unwrap();
+        let noisy = XorArbiterPuf::new(8, 1, 7, 0.1).unwrap();
+        let mut flips = 0;
+        for i in 0..100u64 {
+            let c = hex::encode([&i.to_be_bytes()[..], &[0u8; 4]].concat());
+            let (a, b) = (exact.response(&c).unwrap(), noisy.response(&c).unwrap());
+            flips += a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum::<u32>();
+        }
+        // 9600 bits at a 10% flip rate
+        assert!((700..1300).contains(&flips), "{flips} flips");
+    }
+}
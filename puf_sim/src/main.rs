@@ -0,0 +1,73 @@
+use clap::Parser;
+use puf_sim::{DEFAULT_BITS, XorArbiterPuf};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+/// Drop-in replacement for the `puf_py` server backed by a simulated XOR arbiter PUF.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct CliArgs {
+    #[arg(short, long, default_value = "0.0.0.0:12345")]
+    bind: SocketAddr,
+
+    #[arg(short, long, help = "Device seed; every seed simulates another device", default_value = "1")]
+    seed: u64,
+
+    #[arg(short, long, help = "Arbiter chains XORed into each response bit", default_value = "1")]
+    k: usize,
+
+    #[arg(short, long, help = "Bits of a sub-challenge, a divisor of 32", default_value = "8")]
+    n: usize,
+
+    #[arg(long, help = "Chance that a response bit flips on a reading", default_value = "0")]
+    noise: f64,
+
+    #[arg(long, help = "Bits of a challenge, a multiple of 8", default_value_t = DEFAULT_BITS)]
+    challenge_bits: usize,
+
+    #[arg(long, help = "Bits of a response, a multiple of 8", default_value_t = DEFAULT_BITS)]
+    response_bits: usize,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or("puf_sim=info".parse().unwrap()))
+        .init();
+    let args = CliArgs::parse();
+    let puf = Arc::new(XorArbiterPuf::new(args.n, args.k, args.seed, args.noise)?.with_widths(args.challenge_bits, args.response_bits)?);
+
+    let listener = TcpListener::bind(args.bind).await?;
+    info!(
+        "Simulated {}-XOR arbiter PUF (n={}, seed={}, noise={}, {}-bit challenges, {}-bit responses) listening on {}",
+        args.k, args.n, args.seed, args.noise, args.challenge_bits, args.response_bits, args.bind
+    );
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let puf = puf.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, &puf).await {
+                warn!("Client {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Answer hex challenges with hex responses until the client disconnects.
+async fn handle_client(mut stream: TcpStream, puf: &XorArbiterPuf) -> anyhow::Result<()> {
+    let mut buf = vec![0u8; puf.challenge_bits() / 4];
+    loop {
+        match stream.read_exact(&mut buf).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        let response = puf.calculate(std::str::from_utf8(&buf)?.trim())?;
+        stream.write_all(response.as_bytes()).await?;
+    }
+}
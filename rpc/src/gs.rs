@@ -1,12 +1,24 @@
+use crate::GsCertificate;
+
 #[tarpc::service]
 pub trait GsRpc {
     async fn get_gs_pubkey() -> String;
     async fn authenticate_uav_phase1(req: UavAuthRequest1) -> Option<UavAuthResponse1>;
-    async fn authenticate_uav_phase2(req: UavAuthRequest2) -> Option<UavAuthResponse2>;
+    async fn authenticate_uav_phase2(req: UavAuthRequest2) -> Result<UavAuthResponse2, BatchAuthStatus>;
     async fn get_all_uav_id(id: String) -> Vec<String>;
     async fn communicate_uavs(req: UavCommRequest) -> Option<UavCommResponse>;
-    async fn batch_authenticate_uavs_phase1(reqs: Vec<String>) -> Option<Vec<String>>;
-    async fn batch_authenticate_uavs_phase2(reqs: Vec<UavAuthRequest2>) -> Option<UavAuthResponse2>;
+    async fn fetch_group_keys(req: GroupKeyFetchRequest) -> Option<Vec<UavCommResponse>>;
+    async fn create_group(req: GroupCreateRequest) -> Option<UavCommResponse>;
+    async fn join_group(req: GroupRequest) -> Option<UavCommResponse>;
+    async fn leave_group(req: GroupRequest) -> bool;
+    async fn evict_group_member(req: GroupEvictRequest) -> bool;
+    async fn dissolve_group(req: GroupRequest) -> bool;
+    async fn get_group_epoch(req: GroupRequest) -> Option<u64>;
+    async fn batch_authenticate_uavs_phase1(req: BatchAuthRequest1) -> BatchAuthResponse1;
+    async fn batch_authenticate_uavs_phase2(req: BatchAuthRequest2) -> BatchAuthResponse2;
+    async fn get_batch_auth_metrics() -> BatchAuthMetrics;
+    async fn send_secure(uid: String, seq: u64, ciphertext: Vec<u8>) -> bool;
+    async fn poll_secure(req: SecurePollRequest) -> Option<Vec<SecureFrame>>;
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -20,6 +32,8 @@ pub struct UavAuthResponse1 {
     pub x: String,
     pub sigma_g: String,
     pub gs_pubkey: String,
+    /// TA certificate of `gs_pubkey`.
+    pub gs_cert: GsCertificate,
     pub t_g: i64,
 }
 
@@ -34,13 +48,203 @@ pub struct UavAuthRequest2 {
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct UavAuthResponse2 {}
 
+/// Outcome of phase 2 for one identity, in a single or batch authentication call.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchAuthStatus {
+    Ok,
+    /// The uid is not registered with the GS.
+    UnknownUav,
+    /// No phase 1 session is pending for the uid.
+    NoSession,
+    /// `t_u` is outside the freshness window.
+    Stale,
+    /// A point in the request failed to decode.
+    Malformed,
+    /// The signature failed verification.
+    InvalidSignature,
+    /// The verification queue of the GS was full; the phase 1 session is kept for a retry.
+    Busy,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct BatchAuthRequest1 {
+    pub reqs: Vec<UavAuthRequest1>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct BatchAuthItem1 {
+    pub uid: String,
+    pub status: BatchAuthStatus,
+    pub response: Option<UavAuthResponse1>,
+}
+
+/// One item per request, in request order.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct BatchAuthResponse1 {
+    pub items: Vec<BatchAuthItem1>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct BatchAuthRequest2 {
+    pub reqs: Vec<UavAuthRequest2>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct BatchAuthItem2 {
+    pub uid: String,
+    pub status: BatchAuthStatus,
+}
+
+/// One item per request, in request order.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct BatchAuthResponse2 {
+    pub items: Vec<BatchAuthItem2>,
+}
+
+/// Counters of batch authentication on the GS since startup.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct BatchAuthMetrics {
+    /// Batches that reached the aggregate pairing check.
+    pub batches: u64,
+    /// Batches whose aggregate check failed and fell back to bisection.
+    pub fallbacks: u64,
+    /// Aggregate checks run on sub-batches during bisection.
+    pub sub_checks: u64,
+    pub requests: u64,
+    pub rejected: u64,
+    /// Requests refused because the verification queue was full.
+    pub busy: u64,
+}
+
+/// Freshness window of the requests a UAV authenticates with its session key.
+///
+/// Their `t_u` is in Unix milliseconds and has to grow with every request of a session; the GS
+/// takes a repeated or older one for a replay.
+pub const REQUEST_WINDOW_MS: i64 = 10_000;
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct UavCommRequest {
+    pub uid: String,
     pub uid_k: Vec<String>,
+    pub t_u: i64,
+    pub mac: String,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct UavCommResponse {
+    pub msg: GroupKeyMessage,
+    pub sigma: String,
+    pub c: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct GroupKeyFetchRequest {
+    pub uid: String,
+    pub t_u: i64,
+    pub mac: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SecurePollRequest {
+    pub uid: String,
+    pub t_u: i64,
+    pub mac: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct GroupCreateRequest {
+    pub uid: String,
+    pub name: String,
+    pub members: Vec<String>,
+    pub t_u: i64,
+    pub mac: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct GroupRequest {
+    pub uid: String,
+    pub name: String,
+    pub t_u: i64,
+    pub mac: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct GroupEvictRequest {
+    pub uid: String,
+    pub name: String,
+    pub target: String,
+    pub t_u: i64,
+    pub mac: String,
+}
+
+/// Group key distribution message, signed by the GS with its BLS key.
+///
+/// Large groups are split into CRT shards sharing one `k_d`; each message carries the
+/// members and `mu` of a single shard only.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct GroupKeyMessage {
+    pub gid: String,
+    pub group_id: String,
+    pub epoch: u64,
+    pub shard: u32,
+    pub shards: u32,
+    pub members: Vec<String>,
     pub mu: String,
-    pub c_m: Vec<String>,
+    pub kcv: String,
+    pub t_g: i64,
+}
+
+/// Hash-to-curve domain separation tag of group key signatures, distinct from the one of
+/// authentication signatures so a signature of one kind never verifies as the other.
+pub const GROUP_KEY_DST: &[u8] = b"EGCDA_GROUP_KEY_BLS12381G1_XMD:BLAKE2b-512_SSWU_RO_";
+
+impl GroupKeyMessage {
+    /// Canonical byte encoding covered by the GS signature.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for field in [self.gid.as_bytes(), self.group_id.as_bytes()] {
+            buf.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            buf.extend_from_slice(field);
+        }
+        buf.extend_from_slice(&self.epoch.to_be_bytes());
+        buf.extend_from_slice(&self.shard.to_be_bytes());
+        buf.extend_from_slice(&self.shards.to_be_bytes());
+        buf.extend_from_slice(&(self.members.len() as u64).to_be_bytes());
+        for uid in &self.members {
+            buf.extend_from_slice(&(uid.len() as u64).to_be_bytes());
+            buf.extend_from_slice(uid.as_bytes());
+        }
+        for field in [self.mu.as_bytes(), self.kcv.as_bytes()] {
+            buf.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            buf.extend_from_slice(field);
+        }
+        buf.extend_from_slice(&self.t_g.to_be_bytes());
+        buf
+    }
+}
+
+/// Default number of members per CRT shard, keeping each `mu_j` around 16 KiB.
+pub const DEFAULT_SHARD_SIZE: usize = 512;
+/// Undelivered group key messages a GS keeps per UAV, all returned by one `fetch_group_keys`.
+pub const MAX_QUEUED_GROUP_KEYS: usize = 64;
+/// JSON bytes per shard member of a group key message: its hex uid and 256-bit share of `mu`.
+const GROUP_KEY_MEMBER_BYTES: usize = 192;
+/// JSON bytes of a group key message besides its members.
+const GROUP_KEY_BASE_BYTES: usize = 1024;
+/// Lower bound of the frame length, leaving room for batch authentication of a few thousand UAVs
+/// at about 1 KiB each, whose frames follow the batch rather than the shard size.
+const MIN_FRAME_LENGTH: usize = 4 << 20;
+
+/// Largest frame on a UAV–GS connection when the GS splits groups into shards of `shard_size`.
+///
+/// The bound is a full mailbox drained by `fetch_group_keys`; both ends must use the same shard size.
+pub fn max_frame_length(shard_size: usize) -> usize {
+    let mailbox = MAX_QUEUED_GROUP_KEYS * (GROUP_KEY_BASE_BYTES + shard_size * GROUP_KEY_MEMBER_BYTES);
+    mailbox.max(MIN_FRAME_LENGTH)
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SecureFrame {
+    pub seq: u64,
+    pub ciphertext: Vec<u8>,
 }
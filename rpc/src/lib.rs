@@ -2,4 +2,5 @@ mod gs;
 mod ta;
 
 pub use gs::*;
+pub use utils::{MAX_PUF_BITS, PufEncoding, PufGeometry};
 pub use ta::*;
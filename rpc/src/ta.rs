@@ -4,27 +4,106 @@ use rug::Integer;
 pub trait TaRpc {
     async fn get_ta_pubkey1() -> String;
     async fn get_ta_pubkey2() -> String;
-    async fn register_gs(req: GsRegisterRequest) -> ();
+    async fn register_gs(req: GsRegisterRequest) -> Option<GsCertificate>;
+    async fn get_gs_pubkey(gid: String) -> Option<String>;
     async fn authenticate_gs(req: GsAuthRequest) -> Option<GsAuthResponse>;
     async fn register_uav_phase1(req: UavRegisterRequest1) -> Option<UavRegisterResponse1>;
     async fn register_uav_phase2(req: UavRegisterRequest2) -> Option<UavRegisterResponse2>;
 }
 
+/// Registration of a ground station; the TA certifies it for the scope its allowlist gives `gid`.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct GsRegisterRequest {
     pub gid: String,
     pub gs_pubkey1: String,
     pub gs_pubkey2: String,
+    /// BLS signature in G1 over [`GsRegisterRequest::pop_bytes`] by the secret key of both public keys.
+    pub pop: String,
+    /// The same signature by the key currently registered for `gid`, required to replace it.
+    pub endorsement: Option<String>,
 }
 
+impl GsRegisterRequest {
+    /// Message signed by the proof of possession and the endorsement.
+    pub fn pop_bytes(&self) -> Vec<u8> {
+        let mut buf = b"gs-pop".to_vec();
+        for field in [&self.gid, &self.gs_pubkey1, &self.gs_pubkey2] {
+            buf.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            buf.extend_from_slice(field.as_bytes());
+        }
+        buf
+    }
+}
+
+/// Certificate scope accepted by every UAV.
+pub const ANY_SCOPE: &str = "*";
+
+/// TA-issued credential binding a ground station to its G1 key, presented to UAVs in phase 1.
+///
+/// `sigma` is a BLS signature in G2 by the TA, verifiable against the TA's G1 public key.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GsCertificate {
+    pub gid: String,
+    pub pk_g1: String,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub scope: String,
+    pub sigma: String,
+}
+
+impl GsCertificate {
+    /// Canonical byte encoding covered by the TA signature.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for field in [self.gid.as_bytes(), self.pk_g1.as_bytes()] {
+            buf.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            buf.extend_from_slice(field);
+        }
+        buf.extend_from_slice(&self.not_before.to_be_bytes());
+        buf.extend_from_slice(&self.not_after.to_be_bytes());
+        buf.extend_from_slice(&(self.scope.len() as u64).to_be_bytes());
+        buf.extend_from_slice(self.scope.as_bytes());
+        buf
+    }
+
+    /// Whether the certificate covers `scope`.
+    pub fn allows(&self, scope: &str) -> bool {
+        self.scope == ANY_SCOPE || self.scope == scope
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct UavRegisterRequest1 {
+    /// Key of a UAV that derives its secret key itself; `None` lets the TA issue a key pair.
+    pub key: Option<UavPublicKey>,
+    /// Geometry of the UAV's PUF, which the TA issues the challenge for and records.
+    #[serde(default)]
+    pub geometry: crate::PufGeometry,
+}
+
+/// Public key of a UAV with a proof that the UAV holds its secret key.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
-pub struct UavRegisterRequest1 {}
+pub struct UavPublicKey {
+    pub pk: String,
+    /// BLS signature in G1 over [`UavPublicKey::pop_bytes`].
+    pub pop: String,
+}
+
+impl UavPublicKey {
+    /// Message signed by the proof of possession.
+    pub fn pop_bytes(pk: &str) -> Vec<u8> {
+        let mut buf = b"uav-pop".to_vec();
+        buf.extend_from_slice(pk.as_bytes());
+        buf
+    }
+}
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct UavRegisterResponse1 {
     pub uid: String,
     pub puf_challenge: String,
-    pub uav_sk: String,
+    /// Secret key issued by the TA, `None` when the UAV brought its own key.
+    pub uav_sk: Option<String>,
     pub uav_pubkey: String,
 }
 
@@ -44,6 +123,10 @@ pub struct GsAuthRequest {
     pub sigma: String,
 }
 
+/// Largest frame on a TA connection: the encrypted UAV list of [`GsAuthResponse`], a JSON array
+/// of about 2 KiB per registered UAV, fits some 60 000 registrations.
+pub const TA_MAX_FRAME_LENGTH: usize = 128 << 20;
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct GsAuthResponse {
     pub sigma_t: String,
@@ -5,10 +5,10 @@ use futures::{future, StreamExt};
 use hex::ToHex;
 use lazy_static::lazy_static;
 use rand::Rng;
-use rpc::TaRpc;
+use rpc::{PufGeometry, TaRpc};
 use rpc_impl::TA;
 use rug::Integer;
-use std::{future::Future, net::SocketAddr};
+use std::{collections::HashMap, future::Future, net::SocketAddr};
 use tarpc::{server, server::Channel, tokio_serde::formats::Json};
 use tracing_subscriber::EnvFilter;
 use utils::abbreviate_key_default;
@@ -29,8 +29,12 @@ pub struct GsInfo {
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 pub struct UavInfo {
     pub uid: String,
-    pub sk: Scalar,
+    /// `None` when the UAV derives its key from its PUF and only registered `pk`.
+    pub sk: Option<Scalar>,
     pub pk: G2Affine,
+    /// Geometry of the UAV's PUF; `c` and `r` are in its widths.
+    #[serde(default)]
+    pub geometry: PufGeometry,
     pub c: String,
     pub r: String,
     pub p: Integer,
@@ -40,8 +44,9 @@ pub struct UavInfo {
 pub struct UavList(DashMap<String, UavInfo>);
 
 const TAG: &[u8] = b"BLS_SIG_BLS12381G1_XMD:BLAKE2b-512_SSWU_RO_NUL_";
-const PUF_INPUT_SIZE: usize = 12;
 const T_MAX: usize = 10;
+/// Validity of a GS certificate in seconds from registration.
+const GS_CERT_TTL: i64 = 24 * 60 * 60;
 
 lazy_static! {
     static ref TA_CONFIG: TAConfig = init_ta_keys();
@@ -76,10 +81,19 @@ async fn main() -> anyhow::Result<()> {
 
     let addr: SocketAddr = ([0, 0, 0, 0], 8090).into();
     let mut listener = tarpc::serde_transport::tcp::listen(&addr, Json::default).await?;
-    listener.config_mut().max_frame_length(usize::MAX);
+    listener.config_mut().max_frame_length(rpc::TA_MAX_FRAME_LENGTH);
     tracing::info!("Listening on port {}", listener.local_addr().port());
 
-    let server = TA::new(TA_CONFIG.clone());
+    // ground stations allowed to register, as a `{ "<gid>": "<scope>" }` JSON map
+    let gs_allowlist: HashMap<String, String> = match std::env::var("TA_GS_ALLOWLIST") {
+        Ok(path) => serde_json::from_reader(std::fs::File::open(&path)?)?,
+        Err(_) => {
+            tracing::warn!("TA_GS_ALLOWLIST is not set, no ground station can register");
+            HashMap::new()
+        }
+    };
+    tracing::info!("{} ground stations on the allowlist", gs_allowlist.len());
+    let server = TA::new(TA_CONFIG.clone()).with_gs_allowlist(gs_allowlist);
 
     listener
         // Ignore accept errors.
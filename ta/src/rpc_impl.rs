@@ -1,15 +1,17 @@
-use crate::{GsInfo, TAConfig, UavInfo, GS_LIST, PUF_INPUT_SIZE, TAG, T_MAX, UAV_LIST};
+use crate::{GsInfo, TAConfig, UavInfo, GS_CERT_TTL, GS_LIST, TAG, T_MAX, UAV_LIST};
 use blake2::{Blake2b512, Digest};
 use blstrs_plus::{
     elliptic_curve::hash2curve::ExpandMsgXmd, group::prime::PrimeCurveAffine, pairing, G1Affine, G1Projective, G2Affine, Scalar,
 };
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use hex::ToHex;
 use lazy_static::lazy_static;
+use rand::RngCore;
 use rpc::*;
 use rug::Integer;
+use std::{collections::HashMap, sync::Arc};
 use tracing::{debug, info, warn};
-use utils::{abbreviate_key_default, encrypt_aes128_gcm, hash_to_prime};
+use utils::{abbreviate_key_default, encrypt_aes128_gcm, hash_to_prime, sign_g2};
 
 lazy_static! {
     static ref state: DashMap<String, UavInfo> = DashMap::new();
@@ -18,14 +20,45 @@ lazy_static! {
 #[derive(Clone)]
 pub struct TA {
     cfg: TAConfig,
+    /// Scope of every ground station allowed to register, by gid.
+    gs_allowlist: Arc<HashMap<String, String>>,
 }
 
 impl TA {
     pub fn new(cfg: TAConfig) -> Self {
-        TA { cfg }
+        TA {
+            cfg,
+            gs_allowlist: Arc::default(),
+        }
+    }
+
+    pub fn with_gs_allowlist(mut self, allowlist: HashMap<String, String>) -> Self {
+        self.gs_allowlist = Arc::new(allowlist);
+        self
     }
 }
 
+/// Compressed G1 point in hex, `None` for anything else.
+fn decode_g1(hex: &str) -> Option<G1Affine> {
+    let bytes: [u8; 48] = hex::decode(hex).ok()?.try_into().ok()?;
+    G1Affine::from_compressed(&bytes).into()
+}
+
+/// Compressed G2 point in hex, `None` for anything else.
+fn decode_g2(hex: &str) -> Option<G2Affine> {
+    let bytes: [u8; 96] = hex::decode(hex).ok()?.try_into().ok()?;
+    G2Affine::from_compressed(&bytes).into()
+}
+
+/// Check the registration signature `sigma` over `req` against `pk`.
+fn verify_gs_signature(req: &rpc::GsRegisterRequest, sigma: &str, pk: &G2Affine) -> bool {
+    let Some(sigma) = decode_g1(sigma) else {
+        return false;
+    };
+    let h = G1Projective::hash::<ExpandMsgXmd<Blake2b512>>(&req.pop_bytes(), TAG);
+    pairing(&sigma, &G2Affine::generator()) == pairing(&h.into(), pk)
+}
+
 impl TaRpc for TA {
     async fn get_ta_pubkey1(self, _context: tarpc::context::Context) -> String {
         hex::encode(self.cfg.pk1.to_compressed())
@@ -35,19 +68,63 @@ impl TaRpc for TA {
         hex::encode(self.cfg.pk2.to_compressed())
     }
 
-    async fn register_gs(self, _context: tarpc::context::Context, req: rpc::GsRegisterRequest) -> () {
-        let gid = req.gid;
+    async fn register_gs(self, _context: tarpc::context::Context, req: rpc::GsRegisterRequest) -> Option<GsCertificate> {
+        let Some(scope) = self.gs_allowlist.get(&req.gid).cloned() else {
+            warn!("GS {} is not on the allowlist", abbreviate_key_default(&req.gid));
+            return None;
+        };
+        let (Some(pk1), Some(pk2)) = (decode_g1(&req.gs_pubkey1), decode_g2(&req.gs_pubkey2)) else {
+            warn!("Invalid public keys in registration of GS {}", abbreviate_key_default(&req.gid));
+            return None;
+        };
+        // both keys share one secret key, and its holder signed them
+        if pairing(&pk1, &G2Affine::generator()) != pairing(&G1Affine::generator(), &pk2) || !verify_gs_signature(&req, &req.pop, &pk2) {
+            warn!("Rejected GS {} without valid proof of possession", abbreviate_key_default(&req.gid));
+            return None;
+        }
+
+        let info = GsInfo {
+            gid: req.gid.clone(),
+            pk1,
+            pk2,
+        };
+        match GS_LIST.entry(req.gid.clone()) {
+            Entry::Occupied(mut entry) => {
+                // replacing the keys of a gid takes the consent of its current key
+                let current = entry.get().pk2;
+                let endorsed = req
+                    .endorsement
+                    .as_ref()
+                    .is_some_and(|sigma| verify_gs_signature(&req, sigma, &current));
+                if current != pk2 && !endorsed {
+                    warn!("GS {} is registered under another key", abbreviate_key_default(&req.gid));
+                    return None;
+                }
+                entry.insert(info);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(info);
+            }
+        }
 
-        let pk1_bytes = hex::decode(req.gs_pubkey1).expect("Failed to decode GS G1 public key");
-        let pk1_array: [u8; 48] = pk1_bytes.try_into().expect("GS G1 public key bytes length mismatch");
-        let pk1 = G1Affine::from_compressed(&pk1_array).expect("Failed to create G1Affine from bytes");
+        // certify the G1 key the GS presents to UAVs in phase 1
+        let not_before = chrono::Utc::now().timestamp();
+        let mut cert = GsCertificate {
+            gid: req.gid,
+            pk_g1: req.gs_pubkey1,
+            not_before,
+            not_after: not_before + GS_CERT_TTL,
+            scope,
+            sigma: String::new(),
+        };
+        cert.sigma = sign_g2(&self.cfg.sk, &cert.signing_bytes()).to_compressed().encode_hex();
 
-        let pk2_bytes = hex::decode(req.gs_pubkey2).expect("Failed to decode GS G2 public key");
-        let pk2_array: [u8; 96] = pk2_bytes.try_into().expect("GS G2 public key bytes length mismatch");
-        let pk2 = G2Affine::from_compressed(&pk2_array).expect("Failed to create G2Affine from bytes");
+        info!("GS registered: {}, scope {}", abbreviate_key_default(&cert.gid), cert.scope);
+        Some(cert)
+    }
 
-        info!("GS registered: {}", abbreviate_key_default(&gid));
-        GS_LIST.insert(gid.clone(), GsInfo { gid, pk2, pk1 });
+    async fn get_gs_pubkey(self, _context: tarpc::context::Context, gid: String) -> Option<String> {
+        GS_LIST.get(&gid).map(|gs_info| hex::encode(gs_info.pk2.to_compressed()))
     }
 
     #[allow(clippy::missing_transmute_annotations)]
@@ -122,29 +199,46 @@ impl TaRpc for TA {
     async fn register_uav_phase1(
         self,
         _context: tarpc::context::Context,
-        _req: rpc::UavRegisterRequest1,
+        req: rpc::UavRegisterRequest1,
     ) -> Option<rpc::UavRegisterResponse1> {
+        if let Err(e) = req.geometry.validate() {
+            warn!("Rejected UAV registration: {}", e);
+            return None;
+        }
+
         let uid = rand::random::<[u8; 32]>();
         let uid = uid.encode_hex::<String>();
 
-        let sk = rand::random::<[u64; 4]>();
-        let sk = Scalar::from_raw_unchecked(sk);
-
-        let pk = G2Affine::generator() * sk;
+        let (sk, pk) = match req.key {
+            Some(key) => {
+                let Some(pk) = verify_uav_key(&key) else {
+                    warn!("Rejected UAV public key without valid proof of possession");
+                    return None;
+                };
+                (None, pk)
+            }
+            None => {
+                let sk = Scalar::from_raw_unchecked(rand::random::<[u64; 4]>());
+                (Some(sk), G2Affine::from(G2Affine::generator() * sk))
+            }
+        };
 
-        let puf_challenge = rand::random::<[u8; PUF_INPUT_SIZE]>().encode_hex::<String>();
+        let mut puf_challenge = vec![0u8; req.geometry.challenge_bytes()];
+        rand::thread_rng().fill_bytes(&mut puf_challenge);
+        let puf_challenge = puf_challenge.encode_hex::<String>();
 
         let uav_info = UavInfo {
             uid: uid.clone(),
             sk,
-            pk: pk.into(),
+            pk,
+            geometry: req.geometry,
             c: puf_challenge.clone(),
             r: String::default(),
             p: Integer::default(),
         };
         debug!("uav info: {:?}", uav_info);
 
-        let sk_hex = sk.to_be_bytes().encode_hex::<String>();
+        let sk_hex = sk.map(|sk| sk.to_be_bytes().encode_hex::<String>());
         let pk_hex = pk.to_compressed().encode_hex::<String>();
 
         if state.contains_key(&uid) {
@@ -170,12 +264,16 @@ impl TaRpc for TA {
         let uid = req.uid;
         let puf_response = req.puf_response;
 
-        let p = hash_to_prime(puf_response.clone() + &uid);
-
         let Some((_, mut uav_info)) = state.remove(&uid) else {
             warn!("UAV with uid {} not found", uid);
             return None;
         };
+        if let Err(e) = uav_info.geometry.decode_response(&puf_response) {
+            warn!("Rejected UAV {}: {}", abbreviate_key_default(&uid), e);
+            return None;
+        }
+
+        let p = hash_to_prime(puf_response.clone() + &uid);
 
         uav_info.p = p;
         uav_info.r = puf_response;
@@ -187,6 +285,14 @@ impl TaRpc for TA {
     }
 }
 
+/// The key in `key` if its proof of possession verifies.
+fn verify_uav_key(key: &UavPublicKey) -> Option<G2Affine> {
+    let pk = Option::from(G2Affine::from_compressed_hex(&key.pk))?;
+    let pop = Option::from(G1Affine::from_compressed_hex(&key.pop))?;
+    let h = G1Projective::hash::<ExpandMsgXmd<Blake2b512>>(&UavPublicKey::pop_bytes(&key.pk), TAG);
+    (pairing(&pop, &G2Affine::generator()) == pairing(&h.into(), &pk)).then_some(pk)
+}
+
 fn transmute_uav_info(uav: &UavInfo, cfg: &TAConfig) -> GsAuthResponseStruct {
     let r = hex::decode(&uav.r).unwrap();
     let mut r_buf = [0u8; 64];
@@ -206,3 +312,60 @@ fn transmute_uav_info(uav: &UavInfo, cfg: &TAConfig) -> GsAuthResponseStruct {
         p: uav.p.clone(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs_plus::ff::Field;
+
+    fn sign(req: &rpc::GsRegisterRequest, sk: Scalar) -> String {
+        let h = G1Projective::hash::<ExpandMsgXmd<Blake2b512>>(&req.pop_bytes(), TAG);
+        (h * sk).to_compressed().encode_hex()
+    }
+
+    /// Registration of `gid` under `sk` with its proof of possession.
+    fn request(gid: &str, sk: Scalar) -> rpc::GsRegisterRequest {
+        let mut req = rpc::GsRegisterRequest {
+            gid: gid.to_string(),
+            gs_pubkey1: G1Affine::from(G1Affine::generator() * sk).to_compressed().encode_hex(),
+            gs_pubkey2: G2Affine::from(G2Affine::generator() * sk).to_compressed().encode_hex(),
+            pop: String::new(),
+            endorsement: None,
+        };
+        req.pop = sign(&req, sk);
+        req
+    }
+
+    #[tokio::test]
+    async fn test_register_gs_checks_allowlist_and_keys() {
+        let sk_t = Scalar::random(rand::thread_rng());
+        let cfg = TAConfig {
+            sk: sk_t,
+            pk1: (G1Affine::generator() * sk_t).into(),
+            pk2: (G2Affine::generator() * sk_t).into(),
+        };
+        let ta = TA::new(cfg).with_gs_allowlist(HashMap::from([("gs-a".to_string(), "fleet-1".to_string())]));
+        let register = |req| ta.clone().register_gs(tarpc::context::current(), req);
+        let sk = Scalar::random(rand::thread_rng());
+
+        assert!(register(request("gs-b", sk)).await.is_none());
+        let mut malformed = request("gs-a", sk);
+        malformed.gs_pubkey2 = "not hex".to_string();
+        assert!(register(malformed).await.is_none());
+        let mut forged = request("gs-a", sk);
+        forged.pop = sign(&forged, Scalar::random(rand::thread_rng()));
+        assert!(register(forged).await.is_none());
+
+        // the scope is the allowlist's, and the same key may register again
+        assert_eq!(register(request("gs-a", sk)).await.unwrap().scope, "fleet-1");
+        assert!(register(request("gs-a", sk)).await.is_some());
+
+        // another key takes the endorsement of the registered one
+        let sk2 = Scalar::random(rand::thread_rng());
+        let mut rotate = request("gs-a", sk2);
+        assert!(register(rotate.clone()).await.is_none());
+        rotate.endorsement = Some(sign(&rotate, sk));
+        assert!(register(rotate).await.is_some());
+        assert_eq!(GS_LIST.get("gs-a").unwrap().pk2, G2Affine::from(G2Affine::generator() * sk2));
+    }
+}
@@ -0,0 +1,406 @@
+use crate::{auth::auth_uav, comm::fetch_group_keys, uav_cfg::UavConfig};
+use anyhow::Context;
+use rpc::{GsRpcClient, TaRpcClient};
+use std::{
+    collections::BTreeMap,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tarpc::{client, tokio_serde::formats::Json};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::mpsc,
+    time::Instant,
+};
+use tracing::{info, warn};
+use utils::abbreviate_key_default;
+
+/// First pause before reconnecting to the GS, doubled after every failed attempt.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+/// Upper bound of the reconnect pause.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+/// Re-authenticate once this share, in percent, of the session lifetime has passed.
+const REAUTH_PERCENT: u32 = 80;
+
+/// Settings of the long-running agent.
+#[derive(Debug, Clone)]
+pub(crate) struct AgentConfig {
+    pub gs_addr: String,
+    /// Lifetime of a session key; the agent re-authenticates before it runs out.
+    pub session_ttl: Duration,
+    /// Interval between two checks of the GS mailbox for group key updates.
+    pub group_poll: Duration,
+    pub control_socket: PathBuf,
+    /// Frame length limit of the GS connection, see [`rpc::max_frame_length`].
+    pub max_frame_length: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AgentState {
+    Connecting,
+    Authenticating,
+    Authenticated,
+    Backoff,
+}
+
+/// What the control socket reports on `status`.
+#[derive(Debug, Clone, serde::Serialize)]
+struct AgentStatus {
+    uid: String,
+    gs_addr: String,
+    state: AgentState,
+    /// Unix time of the last successful authentication.
+    authenticated_at: Option<i64>,
+    /// Unix time at which the agent re-authenticates.
+    reauth_at: Option<i64>,
+    authentications: u64,
+    reconnects: u64,
+    /// Latest accepted epoch per group.
+    group_epochs: BTreeMap<String, u64>,
+    last_error: Option<String>,
+}
+
+enum Command {
+    Reauth,
+    Shutdown,
+}
+
+/// Why the agent left an established connection.
+enum Exit {
+    Reconnect,
+    Shutdown,
+}
+
+type SharedStatus = Arc<Mutex<AgentStatus>>;
+
+/// Keep `uav` authenticated to the GS until shut down through the control socket or Ctrl-C.
+///
+/// The agent re-authenticates before the session key expires, picks up group key updates from
+/// its mailbox, and reconnects with exponential backoff when the link to the GS drops.
+pub(crate) async fn run_agent(ta_client: &TaRpcClient, uav: UavConfig, cfg: AgentConfig) -> anyhow::Result<()> {
+    let status: SharedStatus = Arc::new(Mutex::new(AgentStatus {
+        uid: uav.uid.clone(),
+        gs_addr: cfg.gs_addr.clone(),
+        state: AgentState::Connecting,
+        authenticated_at: None,
+        reauth_at: None,
+        authentications: 0,
+        reconnects: 0,
+        group_epochs: BTreeMap::new(),
+        last_error: None,
+    }));
+
+    let listener = bind_control_socket(&cfg.control_socket)?;
+    info!("Agent control socket at {}", cfg.control_socket.display());
+    let (tx, mut rx) = mpsc::channel(8);
+    let control = tokio::spawn(serve_control(listener, status.clone(), tx.clone()));
+    let ctrl_c = tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = tx.send(Command::Shutdown).await;
+        }
+    });
+
+    let mut backoff = RECONNECT_BACKOFF;
+    loop {
+        set_state(&status, AgentState::Connecting);
+        let exit = match connect_gs(&cfg.gs_addr, cfg.max_frame_length).await {
+            Ok(client) => session(&client, ta_client, &uav, &cfg, &status, &mut rx, &mut backoff).await,
+            Err(e) => {
+                record_error(&status, &e);
+                Exit::Reconnect
+            }
+        };
+        if let Exit::Shutdown = exit {
+            break;
+        }
+
+        set_state(&status, AgentState::Backoff);
+        status.lock().unwrap().reconnects += 1;
+        warn!("Link to GS lost, reconnecting in {:?}", backoff);
+        if wait(backoff, &mut rx).await {
+            break;
+        }
+        backoff = next_backoff(backoff);
+    }
+
+    info!("Agent shutting down");
+    control.abort();
+    ctrl_c.abort();
+    let _ = std::fs::remove_file(&cfg.control_socket);
+    Ok(())
+}
+
+/// Listen on the control socket at `path`, which only our user may connect to.
+///
+/// A socket left behind by a previous run is replaced; any other file at `path` is kept and the bind fails.
+fn bind_control_socket(path: &Path) -> anyhow::Result<UnixListener> {
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("binding {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+async fn connect_gs(addr: &str, max_frame_length: usize) -> anyhow::Result<GsRpcClient> {
+    let mut transport = tarpc::serde_transport::tcp::connect(addr, Json::default);
+    transport.config_mut().max_frame_length(max_frame_length);
+    Ok(GsRpcClient::new(client::Config::default(), transport.await?).spawn())
+}
+
+/// Pause before the reconnect attempt that follows one after `backoff`.
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(MAX_RECONNECT_BACKOFF)
+}
+
+/// Serve one GS connection: authenticate, then poll for group keys and re-authenticate on schedule.
+///
+/// RPC failures and a rejected authentication end the connection; a refused group key fetch only
+/// triggers a new authentication on the same connection.
+async fn session(
+    client: &GsRpcClient,
+    ta_client: &TaRpcClient,
+    uav: &UavConfig,
+    cfg: &AgentConfig,
+    status: &SharedStatus,
+    rx: &mut mpsc::Receiver<Command>,
+    backoff: &mut Duration,
+) -> Exit {
+    let reauth_after = cfg.session_ttl * REAUTH_PERCENT / 100;
+    let mut poll = tokio::time::interval(cfg.group_poll);
+    loop {
+        set_state(status, AgentState::Authenticating);
+        let reauth_at = match auth_uav(client, uav).await {
+            Ok(true) => {
+                *backoff = RECONNECT_BACKOFF;
+                let t_now = chrono::Utc::now().timestamp();
+                let mut s = status.lock().unwrap();
+                s.state = AgentState::Authenticated;
+                s.authenticated_at = Some(t_now);
+                s.reauth_at = Some(t_now + reauth_after.as_secs() as i64);
+                s.authentications += 1;
+                s.last_error = None;
+                info!("Agent authenticated, next authentication in {:?}", reauth_after);
+                Instant::now() + reauth_after
+            }
+            Ok(false) => {
+                record_error(status, &anyhow::anyhow!("GS rejected the authentication"));
+                return Exit::Reconnect;
+            }
+            Err(e) => {
+                record_error(status, &e);
+                return Exit::Reconnect;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(reauth_at) => break,
+                _ = poll.tick() => match fetch_group_keys(client, ta_client, &uav.uid).await {
+                    Ok(keys) => {
+                        let mut s = status.lock().unwrap();
+                        for key in keys {
+                            info!("Group {} key updated to epoch {}", abbreviate_key_default(&key.group_id), key.epoch);
+                            s.group_epochs.insert(key.group_id, key.epoch);
+                        }
+                    }
+                    Err(e) if e.is::<tarpc::client::RpcError>() => {
+                        record_error(status, &e);
+                        return Exit::Reconnect;
+                    }
+                    Err(e) => {
+                        // e.g. the GS restarted and no longer knows our session
+                        record_error(status, &e);
+                        break;
+                    }
+                },
+                cmd = rx.recv() => match cmd {
+                    Some(Command::Reauth) => break,
+                    Some(Command::Shutdown) | None => return Exit::Shutdown,
+                },
+            }
+        }
+    }
+}
+
+/// Sleep for `delay`; true if a shutdown was requested meanwhile.
+async fn wait(delay: Duration, rx: &mut mpsc::Receiver<Command>) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => return false,
+            cmd = rx.recv() => match cmd {
+                // authenticates right after reconnecting anyway
+                Some(Command::Reauth) => {}
+                Some(Command::Shutdown) | None => return true,
+            },
+        }
+    }
+}
+
+fn set_state(status: &SharedStatus, state: AgentState) {
+    status.lock().unwrap().state = state;
+}
+
+fn record_error(status: &SharedStatus, e: &anyhow::Error) {
+    warn!("Agent: {}", e);
+    status.lock().unwrap().last_error = Some(e.to_string());
+}
+
+/// Answer line-based commands on the control socket: `status`, `reauth` and `shutdown`.
+async fn serve_control(listener: UnixListener, status: SharedStatus, tx: mpsc::Sender<Command>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let (status, tx) = (status.clone(), tx.clone());
+        tokio::spawn(async move {
+            if let Err(e) = control_client(stream, status, tx).await {
+                warn!("Control socket client: {}", e);
+            }
+        });
+    }
+}
+
+async fn control_client(stream: UnixStream, status: SharedStatus, tx: mpsc::Sender<Command>) -> anyhow::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        let reply = match line.trim() {
+            "status" => {
+                let status = status.lock().unwrap().clone();
+                serde_json::to_string(&status)?
+            }
+            "reauth" => {
+                tx.send(Command::Reauth).await?;
+                "ok".to_string()
+            }
+            "shutdown" => {
+                tx.send(Command::Shutdown).await?;
+                "ok".to_string()
+            }
+            other => format!("unknown command: {other}"),
+        };
+        write.write_all(reply.as_bytes()).await?;
+        write.write_all(b"\n").await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs_plus::{group::prime::PrimeCurveAffine, G2Affine, Scalar};
+
+    fn test_status() -> SharedStatus {
+        Arc::new(Mutex::new(AgentStatus {
+            uid: "uav".to_string(),
+            gs_addr: "127.0.0.1:1".to_string(),
+            state: AgentState::Connecting,
+            authenticated_at: None,
+            reauth_at: None,
+            authentications: 0,
+            reconnects: 0,
+            group_epochs: BTreeMap::new(),
+            last_error: None,
+        }))
+    }
+
+    async fn control_request(path: &std::path::Path, command: &str) -> String {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        stream.write_all(format!("{command}\n").as_bytes()).await.unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).await.unwrap();
+        line.trim_end().to_string()
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        assert_eq!(next_backoff(RECONNECT_BACKOFF), RECONNECT_BACKOFF * 2);
+        let mut backoff = RECONNECT_BACKOFF;
+        for _ in 0..16 {
+            let next = next_backoff(backoff);
+            assert!(next >= backoff && next <= MAX_RECONNECT_BACKOFF);
+            backoff = next;
+        }
+        assert_eq!(backoff, MAX_RECONNECT_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_commands() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let (tx, mut rx) = mpsc::channel(8);
+        let task = tokio::spawn(control_client(server, test_status(), tx));
+
+        let (read, mut write) = client.into_split();
+        let mut lines = BufReader::new(read).lines();
+        for (command, reply) in [("reauth", "ok"), ("launch", "unknown command: launch"), ("shutdown", "ok")] {
+            write.write_all(format!("{command}\n").as_bytes()).await.unwrap();
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), reply);
+        }
+        write.write_all(b"status\n").await.unwrap();
+        let status: serde_json::Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(status["uid"], "uav");
+        assert_eq!(status["state"], "connecting");
+
+        assert!(matches!(rx.recv().await, Some(Command::Reauth)));
+        assert!(matches!(rx.recv().await, Some(Command::Shutdown)));
+        drop(write);
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_replaces_only_sockets() {
+        let path = std::env::temp_dir().join(format!("uav-agent-bind-{}.sock", std::process::id()));
+        let listener = bind_control_socket(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        drop(listener);
+
+        // a stale socket is replaced, a regular file is not
+        drop(bind_control_socket(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, b"not a socket").unwrap();
+        assert!(bind_control_socket(&path).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_agent_backs_off_and_shuts_down() {
+        let (transport, _ta) = tarpc::transport::channel::unbounded();
+        let ta_client = TaRpcClient::new(client::Config::default(), transport).spawn();
+        let uav = UavConfig::new("uav".to_string(), Scalar::ONE, G2Affine::generator());
+        let control_socket = std::env::temp_dir().join(format!("uav-agent-test-{}.sock", std::process::id()));
+        let cfg = AgentConfig {
+            // nothing listens on port 1, so every connection attempt fails
+            gs_addr: "127.0.0.1:1".to_string(),
+            session_ttl: Duration::from_secs(3600),
+            group_poll: Duration::from_secs(5),
+            control_socket: control_socket.clone(),
+            max_frame_length: rpc::max_frame_length(rpc::DEFAULT_SHARD_SIZE),
+        };
+        let agent = tokio::spawn(async move { run_agent(&ta_client, uav, cfg).await });
+
+        let status = loop {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if !control_socket.exists() {
+                continue;
+            }
+            let status: serde_json::Value = serde_json::from_str(&control_request(&control_socket, "status").await).unwrap();
+            if status["reconnects"].as_u64().unwrap() >= 1 {
+                break status;
+            }
+        };
+        assert_ne!(status["state"], "authenticated");
+        assert_eq!(status["authentications"], 0);
+        assert!(status["last_error"].is_string());
+
+        assert_eq!(control_request(&control_socket, "shutdown").await, "ok");
+        tokio::time::timeout(Duration::from_secs(5), agent).await.unwrap().unwrap().unwrap();
+        assert!(!control_socket.exists());
+    }
+}
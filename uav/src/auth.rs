@@ -1,26 +1,91 @@
-use crate::{uav_cfg::UavConfig, PUF, TAG, TA_PUBKEY1, UAV_CONFIG, UAV_SESSION_KEYS};
+#[cfg(feature = "fault-injection")]
+use crate::fault::signing_key;
+use crate::{uav_cfg::UavConfig, GS_SCOPE, PUF, SECURE_CHANNELS, TAG, TA_PUBKEY1, UAV_CONFIG, UAV_SESSION_KEYS};
 use blake2::Blake2b512;
 use blstrs_plus::{
     elliptic_curve::hash2curve::ExpandMsgXmd,
     group::{prime::PrimeCurveAffine, Group},
-    G1Affine, G1Projective, Scalar,
+    G1Affine, G1Projective, G2Affine, Scalar,
 };
 use hex::ToHex;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use rpc::*;
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
 use tarpc::context;
 use tracing::{info, warn};
-use utils::{abbreviate_key_default, derive_session_key_from_g1, hash_to_scalar};
+use utils::{abbreviate_key_default, derive_session_key_from_g1, hash_to_scalar, session_mac, verify_g2, Direction, SecureChannel};
+
+/// Phase 2 retries after a "busy" answer; the GS keeps the phase 1 session meanwhile.
+const BUSY_RETRIES: usize = 4;
+/// First backoff after a "busy" answer, doubled on every retry.
+const BUSY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Check that `cert` is a current TA certificate of `gs_pubkey` covering our scope.
+fn verify_gs_certificate(cert: &GsCertificate, gs_pubkey: &str) -> anyhow::Result<()> {
+    if cert.pk_g1 != gs_pubkey {
+        anyhow::bail!("Ground station certificate is for another key");
+    }
+    let t_now = chrono::Utc::now().timestamp();
+    if t_now < cert.not_before || t_now > cert.not_after {
+        anyhow::bail!("Ground station certificate is not valid at {}", t_now);
+    }
+    if let Some(scope) = GS_SCOPE.get() {
+        if !cert.allows(scope) {
+            anyhow::bail!("Ground station is certified for scope {}, not {}", cert.scope, scope);
+        }
+    }
+    let sigma = Option::from(G2Affine::from_compressed_hex(&cert.sigma))
+        .ok_or_else(|| anyhow::anyhow!("Invalid ground station certificate signature"))?;
+    let ta_pk = TA_PUBKEY1.get().expect("TA public key not found");
+    if !verify_g2(ta_pk, &cert.signing_bytes(), &sigma) {
+        anyhow::bail!("Ground station certificate verification failed");
+    }
+    Ok(())
+}
 
 pub(crate) async fn auth(client: &GsRpcClient) -> anyhow::Result<()> {
-    let uav = UAV_CONFIG.get().cloned().expect("UAV not found");
-    let uid = uav.uid;
+    let uav = UAV_CONFIG.get().expect("UAV not found");
+    let result = auth_uav(client, uav).await;
+    log_puf_stats();
+    result?;
+    Ok(())
+}
+
+/// Report the PUF connection pool and, when the PUF is read more than once per challenge, bit stability.
+fn log_puf_stats() {
+    let Some(puf) = PUF.get() else {
+        return;
+    };
+    if let Some(metrics) = puf.pool_metrics() {
+        info!("PUF pool: {}", metrics);
+    }
+    if let Some(report) = puf.reliability() {
+        info!("PUF reliability: {}", report);
+        if !report.unstable_bits.is_empty() {
+            warn!("PUF bit positions {:?} are unstable", report.unstable_bits);
+        }
+    }
+}
+
+/// Key `uav` signs phase 2 with; `fault-injection` builds may swap in a wrong one.
+#[cfg(not(feature = "fault-injection"))]
+fn signing_key(uav: &UavConfig) -> Scalar {
+    uav.sk
+}
+
+/// Authenticate `uav` on its own with the two-phase protocol; returns whether the GS accepted it.
+pub(crate) async fn auth_uav(client: &GsRpcClient, uav: &UavConfig) -> anyhow::Result<bool> {
+    let uid = uav.uid.clone();
     let ctx = context::current();
 
     let resp1 = client.authenticate_uav_phase1(ctx, UavAuthRequest1 { uid: uid.clone() }).await?;
     if resp1.is_none() {
         warn!("UAV not registered or invalid UID");
-        return Ok(());
+        return Ok(false);
     }
     let resp1 = resp1.unwrap();
 
@@ -30,6 +95,7 @@ pub(crate) async fn auth(client: &GsRpcClient) -> anyhow::Result<()> {
     if (chrono::Utc::now().timestamp() - resp1.t_g).abs() > 10 {
         anyhow::bail!("Ground station authentication request is too old");
     }
+    verify_gs_certificate(&resp1.gs_cert, &resp1.gs_pubkey)?;
 
     let puf_response = PUF.get().unwrap().calculate(&challenge).await?;
     let r = hex::decode(&puf_response)?;
@@ -71,8 +137,8 @@ pub(crate) async fn auth(client: &GsRpcClient) -> anyhow::Result<()> {
     let h_i = G1Projective::hash::<ExpandMsgXmd<Blake2b512>>(&buf, TAG);
     let shared = G1Affine::from(G1Projective::from(x) * r_scalar);
     let ssk_g_u = derive_session_key_from_g1(&shared);
-    UAV_SESSION_KEYS.insert(uid.clone(), hex::encode(ssk_g_u));
-    let sigma = h_i * uav.sk;
+    establish_session(&uid, &ssk_g_u);
+    let sigma = h_i * signing_key(uav);
 
     let req2 = UavAuthRequest2 {
         uid: uid.clone(),
@@ -80,34 +146,103 @@ pub(crate) async fn auth(client: &GsRpcClient) -> anyhow::Result<()> {
         g_r: g_r.to_compressed().encode_hex::<String>(),
         t_u,
     };
-    let resp2 = client.authenticate_uav_phase2(ctx, req2).await?;
-
-    if resp2.is_none() {
-        warn!("UAV batch authentication failed in phase2");
-        return Ok(());
+    let mut backoff = BUSY_BACKOFF;
+    for retry in 0..=BUSY_RETRIES {
+        match client.authenticate_uav_phase2(context::current(), req2.clone()).await? {
+            Ok(_) => break,
+            Err(BatchAuthStatus::Busy) if retry < BUSY_RETRIES => {
+                warn!("GS busy, retrying phase2 in {:?}", backoff);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(status) => {
+                warn!("UAV authentication failed in phase2: {:?}", status);
+                end_session(&uid);
+                return Ok(false);
+            }
+        }
     }
     info!("Authentication took: {:?}", start.elapsed());
     info!("UAV authentication successful with uid: {}", abbreviate_key_default(&uid));
-    Ok(())
+    Ok(true)
+}
+
+/// Authenticate `uavs` one after another, in turn, `trials` times in all and return how many the GS accepted.
+///
+/// A rejection counts as a failed trial instead of ending the run, so the success rate on a noisy
+/// PUF can be measured for a given `--puf-reads`. A wrong PUF response already breaks the check of
+/// the GS signature, so failed checks count as well; only a lost GS connection ends the run.
+pub(crate) async fn auth_trials(client: &GsRpcClient, uavs: &[UavConfig], trials: usize) -> anyhow::Result<usize> {
+    let mut accepted = 0;
+    for uav in uavs.iter().cycle().take(trials) {
+        match auth_uav(client, uav).await {
+            Ok(ok) => accepted += ok as usize,
+            Err(e) if e.is::<tarpc::client::RpcError>() => return Err(e),
+            Err(e) => warn!("Authentication of {} failed: {}", abbreviate_key_default(&uav.uid), e),
+        }
+    }
+    log_puf_stats();
+    Ok(accepted)
+}
+
+/// Authenticate `uavs` with concurrent single-UAV calls and return the uids the GS accepted.
+///
+/// Unlike [`batch_auth`], batching is left to the GS, which coalesces concurrent phase 2 requests.
+pub(crate) async fn concurrent_auth(client: &GsRpcClient, uavs: Vec<UavConfig>) -> anyhow::Result<Vec<String>> {
+    let results = futures::future::join_all(uavs.iter().map(|uav| auth_uav(client, uav))).await;
+    log_puf_stats();
+    let mut accepted = Vec::with_capacity(uavs.len());
+    for (uav, ok) in uavs.iter().zip(results) {
+        if ok? {
+            accepted.push(uav.uid.clone());
+        }
+    }
+    if accepted.len() == uavs.len() {
+        info!("Concurrent authentication successful");
+    } else {
+        warn!("Concurrent authentication accepted {}/{} UAVs", accepted.len(), uavs.len());
+    }
+    Ok(accepted)
 }
 
-pub(crate) async fn batch_auth(client: &GsRpcClient, uavs: Vec<UavConfig>) -> anyhow::Result<()> {
+/// Batch-authenticate `uavs` and return the uids the GS accepted.
+pub(crate) async fn batch_auth(client: &GsRpcClient, uavs: Vec<UavConfig>) -> anyhow::Result<Vec<String>> {
     let ctx = context::current();
-    let uids = uavs.par_iter().map(|uav| uav.uid.clone()).collect::<Vec<_>>();
-    let responses = client
-        .batch_authenticate_uavs_phase1(ctx, uids)
-        .await?
-        .ok_or(anyhow::anyhow!("No response from GS in phase 1"))?;
+    let reqs = uavs.iter().map(|uav| UavAuthRequest1 { uid: uav.uid.clone() }).collect();
+    let resp1 = client.batch_authenticate_uavs_phase1(ctx, BatchAuthRequest1 { reqs }).await?;
+    if resp1.items.len() != uavs.len() {
+        anyhow::bail!("GS returned {} results for {} UAVs in phase1", resp1.items.len(), uavs.len());
+    }
+
+    let (uavs, phase1): (Vec<_>, Vec<_>) = uavs
+        .into_iter()
+        .zip(resp1.items)
+        .filter_map(|(uav, item)| match item.response {
+            Some(resp) if item.status == BatchAuthStatus::Ok => Some((uav, resp)),
+            _ => {
+                warn!(
+                    "Batch authentication phase1 {:?} for uid: {}",
+                    item.status,
+                    abbreviate_key_default(&uav.uid)
+                );
+                None
+            }
+        })
+        .unzip();
 
-    let phase1 = responses
+    // one GS answers the whole batch, so its certificate is usually checked once
+    phase1
         .iter()
-        .map(|resp| serde_json::from_str::<UavAuthResponse1>(resp).map_err(anyhow::Error::from))
-        .collect::<Result<Vec<_>, _>>()?;
+        .map(|resp| (&resp.gs_cert, &resp.gs_pubkey))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .try_for_each(|(cert, gs_pubkey)| verify_gs_certificate(cert, gs_pubkey))?;
 
     let rs = futures::future::join_all(phase1.iter().map(|resp| PUF.get().unwrap().calculate(&resp.puf_challenge)))
         .await
         .into_iter()
         .collect::<Result<Vec<_>, _>>()?;
+    log_puf_stats();
 
     let r_scalars = rs
         .par_iter()
@@ -172,7 +307,7 @@ pub(crate) async fn batch_auth(client: &GsRpcClient, uavs: Vec<UavConfig>) -> an
             buf.extend_from_slice(uav.uid.as_bytes());
             buf.extend_from_slice(&t_u.to_be_bytes());
             let h_i = G1Projective::hash::<ExpandMsgXmd<Blake2b512>>(&buf, TAG);
-            (G1Affine::from(h_i), G1Affine::from(h_i * uav.sk))
+            (G1Affine::from(h_i), G1Affine::from(h_i * signing_key(uav)))
         })
         .collect::<Vec<_>>();
 
@@ -184,7 +319,7 @@ pub(crate) async fn batch_auth(client: &GsRpcClient, uavs: Vec<UavConfig>) -> an
             let x = G1Affine::from_compressed_hex(&resp.x).expect("Invalid GS nonce point");
             let shared = G1Affine::from(G1Projective::from(x) * *r_scalar);
             let ssk_g_u = derive_session_key_from_g1(&shared);
-            UAV_SESSION_KEYS.insert(uav.uid.clone(), hex::encode(ssk_g_u));
+            establish_session(&uav.uid, &ssk_g_u);
         });
 
     let reqs = sigmas
@@ -199,13 +334,121 @@ pub(crate) async fn batch_auth(client: &GsRpcClient, uavs: Vec<UavConfig>) -> an
         })
         .collect::<Vec<_>>();
 
-    let resp2 = client.batch_authenticate_uavs_phase2(ctx, reqs).await?;
+    let total = uavs.len();
+    let mut backoff = BUSY_BACKOFF;
+    let mut resp2 = client
+        .batch_authenticate_uavs_phase2(ctx, BatchAuthRequest2 { reqs: reqs.clone() })
+        .await?;
+    for _ in 0..BUSY_RETRIES {
+        if resp2.items.is_empty() || !resp2.items.iter().all(|item| item.status == BatchAuthStatus::Busy) {
+            break;
+        }
+        warn!("GS busy, retrying batch phase2 in {:?}", backoff);
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        resp2 = client
+            .batch_authenticate_uavs_phase2(context::current(), BatchAuthRequest2 { reqs: reqs.clone() })
+            .await?;
+    }
+    if resp2.items.len() != total {
+        anyhow::bail!("GS returned {} results for {} UAVs in phase2", resp2.items.len(), total);
+    }
 
-    if resp2.is_none() {
-        warn!("Batch authentication failed in phase2");
-        return Ok(());
+    let mut accepted = Vec::with_capacity(total);
+    for item in resp2.items {
+        if item.status == BatchAuthStatus::Ok {
+            accepted.push(item.uid);
+        } else {
+            warn!(
+                "Batch authentication phase2 {:?} for uid: {}",
+                item.status,
+                abbreviate_key_default(&item.uid)
+            );
+            end_session(&item.uid);
+        }
     }
+    if accepted.len() == total {
+        info!("Batch authentication successful");
+    } else {
+        warn!("Batch authentication accepted {}/{} UAVs", accepted.len(), total);
+    }
+    Ok(accepted)
+}
 
-    info!("Batch authentication successful");
-    Ok(())
+/// Record the session key agreed with the GS and start a fresh secure channel under it.
+fn establish_session(uid: &str, ssk_g_u: &[u8; 16]) {
+    UAV_SESSION_KEYS.insert(uid.to_string(), hex::encode(ssk_g_u));
+    SECURE_CHANNELS.insert(uid.to_string(), SecureChannel::new(uid, ssk_g_u, Direction::UavToGs));
+}
+
+fn end_session(uid: &str) {
+    UAV_SESSION_KEYS.remove(uid);
+    SECURE_CHANNELS.remove(uid);
+}
+
+/// Session key agreed with the GS for `uid`.
+pub(crate) fn session_key(uid: &str) -> anyhow::Result<[u8; 16]> {
+    let key = UAV_SESSION_KEYS
+        .get(uid)
+        .ok_or_else(|| anyhow::anyhow!("no session key for uid {uid}, authenticate first"))?;
+    hex::decode(key.value())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("session key length mismatch"))
+}
+
+/// `t_u` of a request authenticated with a session key: Unix milliseconds, strictly increasing
+/// within the process so that the GS never takes a request for a replay of an earlier one.
+pub(crate) fn request_time() -> i64 {
+    static LAST: AtomicI64 = AtomicI64::new(0);
+    let t_now = chrono::Utc::now().timestamp_millis();
+    let last = LAST
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| Some(t_now.max(last + 1)))
+        .unwrap();
+    t_now.max(last + 1)
+}
+
+/// Hex MAC proving the session of `uid` over `label`, `uid`, `t_u` and `extra`.
+pub(crate) fn request_mac(label: &[u8], uid: &str, t_u: i64, extra: &[&[u8]]) -> anyhow::Result<String> {
+    let ssk_g_u = session_key(uid)?;
+    let t_u = t_u.to_be_bytes();
+    let mut parts: Vec<&[u8]> = Vec::with_capacity(extra.len() + 3);
+    parts.push(label);
+    parts.push(uid.as_bytes());
+    parts.push(&t_u);
+    parts.extend_from_slice(extra);
+    Ok(hex::encode(session_mac(&ssk_g_u, &parts)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs_plus::ff::Field;
+    use utils::sign_g2;
+
+    #[test]
+    fn test_renewed_certificate_is_accepted() {
+        let sk_t = Scalar::random(rand::thread_rng());
+        TA_PUBKEY1.set((G1Affine::generator() * sk_t).into()).unwrap();
+        let certify = |not_before: i64| {
+            let mut cert = GsCertificate {
+                gid: "gs".to_string(),
+                pk_g1: "gs-key".to_string(),
+                not_before,
+                not_after: not_before + 60,
+                scope: ANY_SCOPE.to_string(),
+                sigma: String::new(),
+            };
+            cert.sigma = sign_g2(&sk_t, &cert.signing_bytes()).to_compressed().encode_hex();
+            cert
+        };
+
+        let t_now = chrono::Utc::now().timestamp();
+        let expired = certify(t_now - 120);
+        assert!(verify_gs_certificate(&expired, "gs-key").is_err());
+
+        // the GS registered again under the same key before it ran out
+        let renewed = certify(t_now - 45);
+        assert!(verify_gs_certificate(&renewed, "gs-key").is_ok());
+        assert!(verify_gs_certificate(&renewed, "other-key").is_err());
+    }
 }
@@ -0,0 +1,67 @@
+use crate::{
+    auth::{request_mac, request_time},
+    SECURE_CHANNELS,
+};
+use dashmap::mapref::one::RefMut;
+use rpc::{GsRpcClient, SecurePollRequest};
+use tarpc::context;
+use utils::SecureChannel;
+
+/// Client side of the authenticated UAV <-> GS channel.
+///
+/// The channel state lives with the session, so every client opened during one session continues
+/// its sequence numbers instead of reusing nonces under the same key.
+pub struct SecureClient {
+    client: GsRpcClient,
+    uid: String,
+}
+
+impl SecureClient {
+    /// Open the channel of `uid` using the session key agreed during authentication.
+    pub fn open(client: &GsRpcClient, uid: &str) -> anyhow::Result<Self> {
+        if !SECURE_CHANNELS.contains_key(uid) {
+            anyhow::bail!("no session for uid {uid}, authenticate first");
+        }
+        Ok(Self {
+            client: client.clone(),
+            uid: uid.to_string(),
+        })
+    }
+
+    /// Encrypt and send one frame to the GS.
+    pub async fn send(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let (seq, ciphertext) = self.channel()?.seal(data)?;
+        let accepted = self
+            .client
+            .send_secure(context::current(), self.uid.clone(), seq, ciphertext)
+            .await?;
+        if !accepted {
+            anyhow::bail!("GS rejected secure frame {seq}");
+        }
+        Ok(())
+    }
+
+    /// Fetch and decrypt every frame the GS has queued for this UAV.
+    pub async fn poll(&mut self) -> anyhow::Result<Vec<Vec<u8>>> {
+        let t_u = request_time();
+        let mac = request_mac(b"poll_secure", &self.uid, t_u, &[])?;
+        let req = SecurePollRequest {
+            uid: self.uid.clone(),
+            t_u,
+            mac,
+        };
+        let frames = self
+            .client
+            .poll_secure(context::current(), req)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("GS refused to poll the secure channel"))?;
+        let mut channel = self.channel()?;
+        frames.into_iter().map(|frame| channel.open(frame.seq, &frame.ciphertext)).collect()
+    }
+
+    fn channel(&self) -> anyhow::Result<RefMut<'static, String, SecureChannel>> {
+        SECURE_CHANNELS
+            .get_mut(&self.uid)
+            .ok_or_else(|| anyhow::anyhow!("session of uid {} ended", self.uid))
+    }
+}
@@ -1,27 +1,192 @@
-use crate::{PUF, UAV_AUTH_LIST, UAV_CONFIG};
+use crate::{
+    auth::{request_mac, request_time},
+    GROUP_EPOCHS, GS_PUBKEYS, PUF, UAV_AUTH_LIST, UAV_CONFIG,
+};
+use blake2::Blake2b512;
+use blstrs_plus::{elliptic_curve::hash2curve::ExpandMsgXmd, group::prime::PrimeCurveAffine, pairing, G1Affine, G1Projective, G2Affine};
+use dashmap::mapref::entry::Entry;
 use rpc::*;
 use rug::Integer;
 use tarpc::context;
-use tracing::info;
-use utils::{abbreviate_key_default, hash_to_prime};
+use tracing::{info, warn};
+use utils::{abbreviate_key_default, hash_to_prime, key_check_value};
 
-pub async fn comm_with_uavs(client: &GsRpcClient) -> anyhow::Result<()> {
+/// Maximum age in seconds of a group key message returned directly to the requester.
+const T_MAX: i64 = 10;
+/// Maximum age in seconds of a group key message picked up from the GS mailbox.
+const GROUP_KEY_TTL: i64 = 300;
+
+/// A group key derived from a verified distribution message.
+#[derive(Debug, Clone)]
+pub struct GroupKey {
+    pub group_id: String,
+    pub epoch: u64,
+    pub k_d: Integer,
+}
+
+pub async fn comm_with_uavs(client: &GsRpcClient, ta_client: &TaRpcClient) -> anyhow::Result<()> {
+    let uid = UAV_CONFIG.get().unwrap().uid.clone();
     let uid_k = UAV_AUTH_LIST.lock().unwrap().clone();
+
+    let k_d = request_group_key(client, ta_client, &uid, uid_k).await?;
+    info!("Group key: {}", abbreviate_key_default(&k_d.to_string_radix(16)));
+
+    Ok(())
+}
+
+/// Ask the GS to form a group of `uid_k` on behalf of `uid` and derive the group key.
+pub async fn request_group_key(client: &GsRpcClient, ta_client: &TaRpcClient, uid: &str, uid_k: Vec<String>) -> anyhow::Result<Integer> {
+    let t_u = request_time();
+    let extra = uid_k.iter().map(|uid| uid.as_bytes()).collect::<Vec<_>>();
+    let mac = request_mac(b"communicate_uavs", uid, t_u, &extra)?;
+
     let resp = client
-        .communicate_uavs(context::current(), UavCommRequest { uid_k })
+        .communicate_uavs(
+            context::current(),
+            UavCommRequest {
+                uid: uid.to_string(),
+                uid_k,
+                t_u,
+                mac,
+            },
+        )
         .await?
         .ok_or(anyhow::anyhow!("No response from GS"))?;
 
-    let mu = Integer::from_str_radix(&resp.mu, 16)?;
-    let c_1 = resp.c_m.first().ok_or(anyhow::anyhow!("Empty c_m"))?.clone();
+    Ok(derive_group_key(ta_client, uid, resp, T_MAX).await?.k_d)
+}
 
-    let puf_response = PUF.get().unwrap().calculate(c_1).await?;
+/// Fetch the group keys the GS has distributed to `uid` as a non-requesting member.
+pub async fn fetch_group_keys(client: &GsRpcClient, ta_client: &TaRpcClient, uid: &str) -> anyhow::Result<Vec<GroupKey>> {
+    let t_u = request_time();
+    let mac = request_mac(b"fetch_group_keys", uid, t_u, &[])?;
+    let resps = client
+        .fetch_group_keys(
+            context::current(),
+            GroupKeyFetchRequest {
+                uid: uid.to_string(),
+                t_u,
+                mac,
+            },
+        )
+        .await?
+        .ok_or(anyhow::anyhow!("GS refused group key fetch"))?;
 
-    let p = hash_to_prime(puf_response + &UAV_CONFIG.get().unwrap().uid);
+    // the GS has drained the mailbox already, so one bad message must not cost the others
+    let mut keys = Vec::with_capacity(resps.len());
+    for resp in resps {
+        let group_id = resp.msg.group_id.clone();
+        match derive_group_key(ta_client, uid, resp, GROUP_KEY_TTL).await {
+            Ok(key) => keys.push(key),
+            Err(e) => warn!("Dropping group key message for {}: {}", abbreviate_key_default(&group_id), e),
+        }
+    }
+    Ok(keys)
+}
 
-    let k_d = mu.clone().modulo(&p);
+/// Form a group of every UAV in `uids`, requested by the first one, and check that all members agree on the key.
+pub async fn batch_group(client: &GsRpcClient, ta_client: &TaRpcClient, uids: &[String]) -> anyhow::Result<()> {
+    let requester = uids.first().ok_or(anyhow::anyhow!("Empty group"))?;
+    let k_d = request_group_key(client, ta_client, requester, uids.to_vec()).await?;
 
-    info!("Group key: {}", abbreviate_key_default(&k_d.to_string_radix(16)));
+    let mut agreed = 1;
+    for uid in &uids[1..] {
+        match fetch_group_keys(client, ta_client, uid).await {
+            Ok(keys) if keys.iter().any(|key| key.k_d == k_d) => agreed += 1,
+            Ok(_) => warn!("No matching group key for {}", abbreviate_key_default(uid)),
+            Err(e) => warn!("Group key fetch failed for {}: {}", abbreviate_key_default(uid), e),
+        }
+    }
+    info!("Group key agreed by {}/{} members", agreed, uids.len());
+    Ok(())
+}
+
+/// Verify a group key message addressed to `uid` and derive `k_d` from it.
+///
+/// Messages for an epoch at or below the last one accepted for the same group are rejected.
+pub(crate) async fn derive_group_key(ta_client: &TaRpcClient, uid: &str, resp: UavCommResponse, max_age: i64) -> anyhow::Result<GroupKey> {
+    let gs_pk = gs_pubkey(ta_client, &resp.msg.gid).await?;
+    if let Err(e) = verify_group_key(&resp.msg, &resp.sigma, &gs_pk, max_age) {
+        // The cached key may predate a re-registration of the GS with the TA; look it up once more.
+        GS_PUBKEYS.remove(&resp.msg.gid);
+        let current = gs_pubkey(ta_client, &resp.msg.gid).await?;
+        if current == gs_pk {
+            return Err(e);
+        }
+        verify_group_key(&resp.msg, &resp.sigma, &current, max_age)?;
+    }
+    if !resp.msg.members.iter().any(|m| m == uid) {
+        anyhow::bail!("Group key message does not list this UAV as a member");
+    }
+
+    let mu = Integer::from_str_radix(&resp.msg.mu, 16)?;
+
+    let puf_response = PUF.get().unwrap().calculate(&resp.c).await?;
+
+    let p = hash_to_prime(puf_response + uid);
+
+    let k_d = mu.modulo(&p);
+    if hex::encode(key_check_value(&resp.msg.group_id, resp.msg.epoch, &k_d)) != resp.msg.kcv {
+        anyhow::bail!("Group key check value mismatch");
+    }
+
+    let GroupKeyMessage {
+        group_id,
+        epoch,
+        shard,
+        shards,
+        ..
+    } = resp.msg;
+    match GROUP_EPOCHS.entry((uid.to_string(), group_id.clone())) {
+        Entry::Occupied(mut e) => {
+            if *e.get() >= epoch {
+                anyhow::bail!("Stale group key epoch {} for group {} (current {})", epoch, group_id, e.get());
+            }
+            e.insert(epoch);
+        }
+        Entry::Vacant(e) => {
+            e.insert(epoch);
+        }
+    }
+    if shards > 1 {
+        info!(
+            "Group {} epoch {} key taken from shard {}/{}",
+            abbreviate_key_default(&group_id),
+            epoch,
+            shard + 1,
+            shards
+        );
+    }
+    Ok(GroupKey { group_id, epoch, k_d })
+}
+
+/// Public key of ground station `gid` as registered with the TA, cached until a signature fails to verify against it.
+async fn gs_pubkey(ta_client: &TaRpcClient, gid: &str) -> anyhow::Result<G2Affine> {
+    if let Some(pk) = GS_PUBKEYS.get(gid) {
+        return Ok(*pk);
+    }
+    // a failed TA call is not a failure of the GS link, so it must not surface as an RpcError
+    let pk = ta_client
+        .get_gs_pubkey(context::current(), gid.to_string())
+        .await
+        .map_err(|e| anyhow::anyhow!("TA lookup of GS key failed: {e}"))?
+        .ok_or(anyhow::anyhow!("Ground station not registered with TA"))?;
+    let pk = Option::<G2Affine>::from(G2Affine::from_compressed_hex(&pk)).ok_or(anyhow::anyhow!("Invalid GS public key"))?;
+    GS_PUBKEYS.insert(gid.to_string(), pk);
+    Ok(pk)
+}
 
+/// Check the GS signature and freshness of a group key message.
+fn verify_group_key(msg: &GroupKeyMessage, sigma: &str, gs_pk: &G2Affine, max_age: i64) -> anyhow::Result<()> {
+    if (chrono::Utc::now().timestamp() - msg.t_g).abs() > max_age {
+        anyhow::bail!("Group key message is too old");
+    }
+    let sigma = Option::<G1Affine>::from(G1Affine::from_compressed_hex(sigma)).ok_or(anyhow::anyhow!("Invalid GS group signature"))?;
+    let h = G1Projective::hash::<ExpandMsgXmd<Blake2b512>>(&msg.signing_bytes(), GROUP_KEY_DST);
+    let lhs = pairing(&sigma, &G2Affine::generator());
+    let rhs = pairing(&h.into(), gs_pk);
+    if lhs != rhs {
+        anyhow::bail!("Group key signature verification failed");
+    }
     Ok(())
 }
@@ -0,0 +1,27 @@
+//! Deliberately invalid phase 2 signatures, to exercise the rejection and batch fallback paths of
+//! the GS. Only built with the `fault-injection` feature.
+
+use crate::uav_cfg::UavConfig;
+use blstrs_plus::Scalar;
+use dashmap::DashSet;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref CORRUPT_UIDS: DashSet<String> = DashSet::new();
+}
+
+/// Sign phase 2 of every identity in `uavs` with a wrong key from now on.
+pub(crate) fn corrupt(uavs: &[UavConfig]) {
+    for uav in uavs {
+        CORRUPT_UIDS.insert(uav.uid.clone());
+    }
+}
+
+/// Key `uav` signs phase 2 with, off by one for the identities passed to [`corrupt`].
+pub(crate) fn signing_key(uav: &UavConfig) -> Scalar {
+    if CORRUPT_UIDS.contains(&uav.uid) {
+        uav.sk + Scalar::ONE
+    } else {
+        uav.sk
+    }
+}
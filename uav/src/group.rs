@@ -0,0 +1,193 @@
+use crate::{
+    auth::{request_mac, request_time},
+    comm::{derive_group_key, fetch_group_keys, GroupKey},
+};
+use rpc::*;
+use tarpc::context;
+use tracing::{info, warn};
+use utils::abbreviate_key_default;
+
+/// Maximum age in seconds of a group key message returned directly to the requester.
+const T_MAX: i64 = 10;
+
+fn group_request(label: &[u8], uid: &str, name: &str) -> anyhow::Result<GroupRequest> {
+    let t_u = request_time();
+    let mac = request_mac(label, uid, t_u, &[name.as_bytes()])?;
+    Ok(GroupRequest {
+        uid: uid.to_string(),
+        name: name.to_string(),
+        t_u,
+        mac,
+    })
+}
+
+/// Create group `name` owned by `uid` with `members` and derive its first key.
+pub async fn create_group(
+    client: &GsRpcClient,
+    ta_client: &TaRpcClient,
+    uid: &str,
+    name: &str,
+    members: Vec<String>,
+) -> anyhow::Result<GroupKey> {
+    let t_u = request_time();
+    let mut extra = vec![name.as_bytes()];
+    extra.extend(members.iter().map(|uid| uid.as_bytes()));
+    let mac = request_mac(b"create_group", uid, t_u, &extra)?;
+
+    let resp = client
+        .create_group(
+            context::current(),
+            GroupCreateRequest {
+                uid: uid.to_string(),
+                name: name.to_string(),
+                members,
+                t_u,
+                mac,
+            },
+        )
+        .await?
+        .ok_or(anyhow::anyhow!("GS refused to create group {name}"))?;
+    derive_group_key(ta_client, uid, resp, T_MAX).await
+}
+
+/// Join group `name` and derive the key of the new epoch.
+pub async fn join_group(client: &GsRpcClient, ta_client: &TaRpcClient, uid: &str, name: &str) -> anyhow::Result<GroupKey> {
+    let req = group_request(b"join_group", uid, name)?;
+    let resp = client
+        .join_group(context::current(), req)
+        .await?
+        .ok_or(anyhow::anyhow!("GS refused to join group {name}"))?;
+    derive_group_key(ta_client, uid, resp, T_MAX).await
+}
+
+/// Leave group `name`; the remaining members are rekeyed.
+pub async fn leave_group(client: &GsRpcClient, uid: &str, name: &str) -> anyhow::Result<()> {
+    let req = group_request(b"leave_group", uid, name)?;
+    if !client.leave_group(context::current(), req).await? {
+        anyhow::bail!("GS refused to leave group {name}");
+    }
+    Ok(())
+}
+
+/// Evict `target` from group `name`; only the owner may do so.
+pub async fn evict_member(client: &GsRpcClient, uid: &str, name: &str, target: &str) -> anyhow::Result<()> {
+    let t_u = request_time();
+    let mac = request_mac(b"evict_group_member", uid, t_u, &[name.as_bytes(), target.as_bytes()])?;
+    let req = GroupEvictRequest {
+        uid: uid.to_string(),
+        name: name.to_string(),
+        target: target.to_string(),
+        t_u,
+        mac,
+    };
+    if !client.evict_group_member(context::current(), req).await? {
+        anyhow::bail!("GS refused to evict {} from group {name}", abbreviate_key_default(target));
+    }
+    Ok(())
+}
+
+/// Dissolve group `name`; only the owner may do so.
+pub async fn dissolve_group(client: &GsRpcClient, uid: &str, name: &str) -> anyhow::Result<()> {
+    let req = group_request(b"dissolve_group", uid, name)?;
+    if !client.dissolve_group(context::current(), req).await? {
+        anyhow::bail!("GS refused to dissolve group {name}");
+    }
+    Ok(())
+}
+
+/// Current epoch of group `name` as seen by member `uid`.
+pub async fn group_epoch(client: &GsRpcClient, uid: &str, name: &str) -> anyhow::Result<u64> {
+    let req = group_request(b"get_group_epoch", uid, name)?;
+    client
+        .get_group_epoch(context::current(), req)
+        .await?
+        .ok_or(anyhow::anyhow!("Not a member of group {name}"))
+}
+
+/// Latest key of group `name` waiting in the mailbox of `uid`, if any.
+async fn latest_key(client: &GsRpcClient, ta_client: &TaRpcClient, uid: &str, name: &str) -> anyhow::Result<Option<GroupKey>> {
+    let keys = fetch_group_keys(client, ta_client, uid).await?;
+    Ok(keys.into_iter().filter(|key| key.group_id == name).max_by_key(|key| key.epoch))
+}
+
+/// Check that every member except `holder` picks up `key` and reports its epoch.
+async fn check_agreement(
+    client: &GsRpcClient,
+    ta_client: &TaRpcClient,
+    name: &str,
+    members: &[String],
+    holder: Option<&str>,
+    key: &GroupKey,
+) -> anyhow::Result<()> {
+    let mut agreed = 0;
+    for uid in members {
+        if Some(uid.as_str()) != holder {
+            match latest_key(client, ta_client, uid, name).await? {
+                Some(k) if k.epoch == key.epoch && k.k_d == key.k_d => {}
+                _ => {
+                    warn!(
+                        "Member {} missed epoch {} of group {}",
+                        abbreviate_key_default(uid),
+                        key.epoch,
+                        name
+                    );
+                    continue;
+                }
+            }
+        }
+        if group_epoch(client, uid, name).await? == key.epoch {
+            agreed += 1;
+        }
+    }
+    info!("Group {} epoch {} agreed by {}/{} members", name, key.epoch, agreed, members.len());
+    if agreed != members.len() {
+        anyhow::bail!("Group {name} members disagree on epoch {}", key.epoch);
+    }
+    Ok(())
+}
+
+/// Exercise the lifecycle of group `name` over `uids` and check that every membership change rekeys it.
+///
+/// The first half of `uids` creates the group and the rest join one by one. Then the last member leaves
+/// and the owner evicts the second member; neither of them may receive the following key. The joins
+/// need a GS group policy that puts all of `uids` in one fleet.
+pub async fn group_churn(client: &GsRpcClient, ta_client: &TaRpcClient, uids: &[String], name: &str) -> anyhow::Result<()> {
+    if uids.len() < 4 {
+        anyhow::bail!("Group churn needs at least 4 UAVs");
+    }
+    let owner = &uids[0];
+    let mut members = uids[..uids.len() / 2].to_vec();
+
+    let key = create_group(client, ta_client, owner, name, members.clone()).await?;
+    check_agreement(client, ta_client, name, &members, Some(owner), &key).await?;
+
+    for uid in &uids[uids.len() / 2..] {
+        let key = join_group(client, ta_client, uid, name).await?;
+        members.push(uid.clone());
+        check_agreement(client, ta_client, name, &members, Some(uid), &key).await?;
+    }
+
+    let leaver = members.pop().unwrap();
+    leave_group(client, &leaver, name).await?;
+    let key = latest_key(client, ta_client, owner, name)
+        .await?
+        .ok_or(anyhow::anyhow!("No rekey after leave"))?;
+    check_agreement(client, ta_client, name, &members, Some(owner), &key).await?;
+
+    let evicted = members.remove(1);
+    evict_member(client, owner, name, &evicted).await?;
+    let key = latest_key(client, ta_client, owner, name)
+        .await?
+        .ok_or(anyhow::anyhow!("No rekey after evict"))?;
+    check_agreement(client, ta_client, name, &members, Some(owner), &key).await?;
+
+    for uid in [&leaver, &evicted] {
+        if latest_key(client, ta_client, uid, name).await?.is_some() {
+            anyhow::bail!("Removed member {} received a key of group {name}", abbreviate_key_default(uid));
+        }
+    }
+
+    dissolve_group(client, owner, name).await?;
+    info!("Group {} dissolved after epoch {}", name, key.epoch);
+    Ok(())
+}
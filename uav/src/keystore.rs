@@ -0,0 +1,429 @@
+use crate::{puf_key::PufKey, uav_cfg::UavConfig};
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use anyhow::Context;
+use argon2::{Algorithm, Argon2, Params, Version};
+use blake2::{Blake2b512, Digest};
+use blstrs_plus::{group::prime::PrimeCurveAffine, G2Affine};
+use hex::ToHex;
+use rand::Rng;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// Format version written into every keystore; files of other versions are refused.
+pub(crate) const KEYSTORE_VERSION: u32 = 1;
+/// Environment variable holding the passphrase of a passphrase-locked keystore.
+pub(crate) const PASSPHRASE_ENV: &str = "UAV_KEYSTORE_PASSPHRASE";
+/// Plaintext identity list written by earlier versions, migrated on first use.
+pub(crate) const LEGACY_PATH: &str = "uav.json";
+
+/// How the keystore key is derived; stored in the clear next to the ciphertext.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Kdf {
+    /// Argon2id over a passphrase.
+    Argon2id {
+        salt: String,
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+    /// BLAKE2b over a secret recovered from the PUF through `key`, so the file only opens on the
+    /// same device and tolerates response noise.
+    PufFuzzy { salt: String, key: PufKey },
+}
+
+/// Cleartext part of the keystore, authenticated as associated data of the ciphertext.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Header {
+    version: u32,
+    kdf: Kdf,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct KeystoreFile {
+    /// JSON text of the [`Header`], used byte for byte as associated data, so fields added to the
+    /// header types later do not change what an existing file was sealed with.
+    header: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Source of the keystore key.
+#[derive(Debug, Clone)]
+pub(crate) enum Unlock {
+    Passphrase(String),
+    Puf,
+}
+
+impl Unlock {
+    /// Passphrase from [`PASSPHRASE_ENV`] when set, the PUF otherwise.
+    pub(crate) fn from_env() -> Self {
+        match std::env::var(PASSPHRASE_ENV) {
+            Ok(passphrase) => Self::Passphrase(passphrase),
+            Err(_) => Self::Puf,
+        }
+    }
+
+    /// Fresh derivation parameters for a new save.
+    async fn new_kdf(&self) -> anyhow::Result<Kdf> {
+        let salt = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        Ok(match self {
+            Self::Passphrase(_) => Kdf::Argon2id {
+                salt,
+                m_cost: Params::DEFAULT_M_COST,
+                t_cost: Params::DEFAULT_T_COST,
+                p_cost: Params::DEFAULT_P_COST,
+            },
+            Self::Puf => Kdf::PufFuzzy {
+                salt,
+                key: PufKey::enroll_secret().await?.0,
+            },
+        })
+    }
+
+    async fn derive_key(&self, kdf: &Kdf) -> anyhow::Result<[u8; 32]> {
+        let mut key = [0u8; 32];
+        match (self, kdf) {
+            (
+                Self::Passphrase(passphrase),
+                Kdf::Argon2id {
+                    salt,
+                    m_cost,
+                    t_cost,
+                    p_cost,
+                },
+            ) => {
+                let params = Params::new(*m_cost, *t_cost, *p_cost, Some(key.len())).map_err(|e| anyhow::anyhow!("{e}"))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), &hex::decode(salt)?, &mut key)
+                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+            }
+            (Self::Puf, Kdf::PufFuzzy { salt, key: puf_key }) => {
+                key = puf_keystore_key(salt, &puf_key.recover_secret().await?)?;
+            }
+            (Self::Passphrase(_), Kdf::PufFuzzy { .. }) => {
+                anyhow::bail!("keystore is locked to the PUF, unset {PASSPHRASE_ENV}")
+            }
+            (Self::Puf, Kdf::Argon2id { .. }) => anyhow::bail!("keystore is locked by a passphrase, set {PASSPHRASE_ENV}"),
+        }
+        Ok(key)
+    }
+}
+
+fn puf_keystore_key(salt: &str, secret: &[u8]) -> anyhow::Result<[u8; 32]> {
+    let mut hasher = Blake2b512::new();
+    hasher.update(b"uav-keystore");
+    hasher.update(hex::decode(salt)?);
+    hasher.update(secret);
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&hasher.finalize()[..32]);
+    Ok(key)
+}
+
+/// One identity as written to the keystore.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct StoredEntry {
+    label: String,
+    #[serde(flatten)]
+    identity: StoredIdentity,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StoredIdentity {
+    /// Secret key issued by the TA.
+    Uav(UavConfig),
+    /// Key derived from the PUF on every unlock; nothing secret is stored.
+    Puf { uid: String, pk: String, key: PufKey },
+}
+
+/// One unlocked identity.
+#[derive(Debug, Clone)]
+pub(crate) struct KeystoreEntry {
+    pub label: String,
+    pub uav: UavConfig,
+    /// Set when `uav.sk` was derived from the PUF.
+    pub puf_key: Option<PufKey>,
+}
+
+impl KeystoreEntry {
+    async fn unlock(stored: StoredEntry) -> anyhow::Result<Self> {
+        let (uav, puf_key) = match stored.identity {
+            StoredIdentity::Uav(uav) => (uav, None),
+            StoredIdentity::Puf { uid, pk, key } => {
+                let pk =
+                    Option::from(G2Affine::from_compressed_hex(&pk)).ok_or_else(|| anyhow::anyhow!("invalid pk of {}", stored.label))?;
+                let sk = key.derive().await?;
+                if G2Affine::from(G2Affine::generator() * sk) != pk {
+                    anyhow::bail!("PUF-derived key of {} does not match its registered pk", stored.label);
+                }
+                (UavConfig::new(uid, sk, pk), Some(key))
+            }
+        };
+        Ok(Self {
+            label: stored.label,
+            uav,
+            puf_key,
+        })
+    }
+
+    fn stored(&self) -> StoredEntry {
+        let identity = match &self.puf_key {
+            None => StoredIdentity::Uav(self.uav.clone()),
+            Some(key) => StoredIdentity::Puf {
+                uid: self.uav.uid.clone(),
+                pk: self.uav.pk.to_compressed().encode_hex(),
+                key: key.clone(),
+            },
+        };
+        StoredEntry {
+            label: self.label.clone(),
+            identity,
+        }
+    }
+}
+
+/// Encrypted, versioned store of the registered UAV identities.
+///
+/// The identities are sealed with AES-256-GCM under a key derived from a passphrase or from a
+/// PUF response. Saves go to a temporary file that is synced and renamed over the keystore, so an
+/// interrupted save leaves the previous version intact.
+pub(crate) struct Keystore {
+    path: PathBuf,
+    unlock: Unlock,
+    entries: Vec<KeystoreEntry>,
+}
+
+impl Keystore {
+    /// An empty keystore to be saved at `path`.
+    pub(crate) fn create(path: impl Into<PathBuf>, unlock: Unlock) -> Self {
+        Self {
+            path: path.into(),
+            unlock,
+            entries: vec![],
+        }
+    }
+
+    /// Decrypt the keystore at `path`.
+    pub(crate) async fn open(path: impl Into<PathBuf>, unlock: Unlock) -> anyhow::Result<Self> {
+        let path = path.into();
+        let data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        let file: KeystoreFile = serde_json::from_slice(&data)?;
+        let header: Header = serde_json::from_str(&file.header)?;
+        if header.version != KEYSTORE_VERSION {
+            anyhow::bail!("unsupported keystore version {} (expected {KEYSTORE_VERSION})", header.version);
+        }
+
+        let key = unlock.derive_key(&header.kdf).await?;
+        let nonce: [u8; 12] = hex::decode(&file.nonce)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("keystore nonce is not 12 bytes"))?;
+        let plaintext = Aes256Gcm::new(&key.into())
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &hex::decode(&file.ciphertext)?,
+                    aad: file.header.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("cannot decrypt {}: wrong key or corrupted file", path.display()))?;
+        let mut entries = vec![];
+        for stored in serde_json::from_slice::<Vec<StoredEntry>>(&plaintext)? {
+            entries.push(KeystoreEntry::unlock(stored).await?);
+        }
+        Ok(Self { path, unlock, entries })
+    }
+
+    /// Open the keystore at `path`, or migrate the plaintext [`LEGACY_PATH`] into a new one there.
+    ///
+    /// After a successful migration the plaintext file is deleted. `None` if neither file exists.
+    pub(crate) async fn open_or_migrate(path: impl Into<PathBuf>, unlock: Unlock) -> anyhow::Result<Option<Self>> {
+        Self::open_or_migrate_from(path.into(), Path::new(LEGACY_PATH), unlock).await
+    }
+
+    async fn open_or_migrate_from(path: PathBuf, legacy_path: &Path, unlock: Unlock) -> anyhow::Result<Option<Self>> {
+        if path.exists() {
+            return Ok(Some(Self::open(path, unlock).await?));
+        }
+        if !legacy_path.exists() {
+            return Ok(None);
+        }
+
+        let uavs: Vec<UavConfig> = serde_json::from_reader(std::fs::File::open(legacy_path)?)?;
+        let mut keystore = Self::create(&path, unlock.clone());
+        keystore.replace(uavs.into_iter().map(|uav| (uav, None)).collect());
+        keystore.save().await?;
+        // only drop the plaintext once the new file is known to open
+        let keystore = Self::open(path, unlock).await?;
+        std::fs::remove_file(legacy_path)?;
+        info!(
+            "Migrated {} identities from {} to {}",
+            keystore.entries.len(),
+            legacy_path.display(),
+            keystore.path.display()
+        );
+        Ok(Some(keystore))
+    }
+
+    pub(crate) fn entries(&self) -> &[KeystoreEntry] {
+        &self.entries
+    }
+
+    /// The identities in order.
+    pub(crate) fn uavs(&self) -> Vec<UavConfig> {
+        self.entries.iter().map(|e| e.uav.clone()).collect()
+    }
+
+    /// The identity labelled `label`.
+    pub(crate) fn get(&self, label: &str) -> Option<&UavConfig> {
+        self.entries.iter().find(|e| e.label == label).map(|e| &e.uav)
+    }
+
+    /// Replace every identity with `uavs`, labelled `uav-0`, `uav-1`, ...
+    pub(crate) fn replace(&mut self, uavs: Vec<(UavConfig, Option<PufKey>)>) {
+        self.entries = uavs
+            .into_iter()
+            .enumerate()
+            .map(|(i, (uav, puf_key))| KeystoreEntry {
+                label: format!("uav-{i}"),
+                uav,
+                puf_key,
+            })
+            .collect();
+    }
+
+    /// Encrypt under a freshly derived key and atomically replace the file.
+    pub(crate) async fn save(&self) -> anyhow::Result<()> {
+        let kdf = self.unlock.new_kdf().await?;
+        let key = self.unlock.derive_key(&kdf).await?;
+        let header = serde_json::to_string(&Header {
+            version: KEYSTORE_VERSION,
+            kdf,
+        })?;
+        let nonce = rand::thread_rng().gen::<[u8; 12]>();
+        let ciphertext = Aes256Gcm::new(&key.into())
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &serde_json::to_vec(&self.entries.iter().map(KeystoreEntry::stored).collect::<Vec<_>>())?,
+                    aad: header.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("keystore encryption failed"))?;
+        let file = KeystoreFile {
+            header,
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        };
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&file)?)
+    }
+}
+
+/// Write `data` to a sibling temporary file, sync it and rename it over `path`.
+fn write_atomic(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut f = options.open(&tmp).with_context(|| format!("creating {}", tmp.display()))?;
+    f.write_all(data)?;
+    f.sync_all()?;
+    drop(f);
+
+    std::fs::rename(&tmp, path)?;
+    // make the rename itself durable
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    std::fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs_plus::Scalar;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("uav-keystore-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn test_uav(uid: &str, sk: u64) -> UavConfig {
+        let sk = Scalar::from(sk);
+        UavConfig::new(uid.to_string(), sk, G2Affine::from(G2Affine::generator() * sk))
+    }
+
+    fn passphrase(passphrase: &str) -> Unlock {
+        Unlock::Passphrase(passphrase.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_save_and_open_round_trip() {
+        let path = test_dir("round-trip").join("uav.keystore");
+        let mut keystore = Keystore::create(&path, passphrase(PASSPHRASE));
+        keystore.replace(vec![(test_uav("a", 3), None), (test_uav("b", 5), None)]);
+        keystore.save().await.unwrap();
+
+        let opened = Keystore::open(&path, passphrase(PASSPHRASE)).await.unwrap();
+        assert_eq!(opened.entries().len(), 2);
+        let b = opened.get("uav-1").unwrap();
+        assert_eq!((b.uid.as_str(), b.sk, b.pk), ("b", Scalar::from(5u64), test_uav("b", 5).pk));
+
+        assert!(Keystore::open(&path, passphrase("wrong")).await.is_err());
+        let e = Keystore::open(&path, Unlock::Puf).await.err().unwrap();
+        assert!(e.to_string().contains(PASSPHRASE_ENV));
+    }
+
+    #[tokio::test]
+    async fn test_header_is_authenticated_as_written() {
+        let path = test_dir("header").join("uav.keystore");
+        let mut keystore = Keystore::create(&path, passphrase(PASSPHRASE));
+        keystore.replace(vec![(test_uav("a", 3), None)]);
+        keystore.save().await.unwrap();
+
+        // the same header in another layout no longer matches the associated data
+        let mut file: KeystoreFile = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let header: Header = serde_json::from_str(&file.header).unwrap();
+        assert_eq!(header.version, KEYSTORE_VERSION);
+        file.header = serde_json::to_string_pretty(&header).unwrap();
+        std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(Keystore::open(&path, passphrase(PASSPHRASE)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrates_plaintext_list_once() {
+        let dir = test_dir("migrate");
+        let (path, legacy_path) = (dir.join("uav.keystore"), dir.join("uav.json"));
+        assert!(Keystore::open_or_migrate_from(path.clone(), &legacy_path, passphrase(PASSPHRASE))
+            .await
+            .unwrap()
+            .is_none());
+
+        std::fs::write(&legacy_path, serde_json::to_vec(&[test_uav("a", 3), test_uav("b", 5)]).unwrap()).unwrap();
+        let migrated = Keystore::open_or_migrate_from(path.clone(), &legacy_path, passphrase(PASSPHRASE))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(migrated.uavs().iter().map(|u| u.uid.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert!(!legacy_path.exists());
+
+        let reopened = Keystore::open_or_migrate_from(path, &legacy_path, passphrase(PASSPHRASE))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reopened.get("uav-0").unwrap().sk, Scalar::from(3u64));
+    }
+}
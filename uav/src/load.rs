@@ -0,0 +1,96 @@
+use crate::{auth::auth_uav, uav_cfg::UavConfig};
+use rpc::GsRpcClient;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tarpc::context;
+use tracing::info;
+
+/// Pause between two probe calls while the load runs.
+const PROBE_INTERVAL: Duration = Duration::from_millis(5);
+
+/// p50, p99 and max of `samples`.
+fn percentiles(samples: &mut [Duration]) -> (Duration, Duration, Duration) {
+    if samples.is_empty() {
+        return Default::default();
+    }
+    samples.sort();
+    let at = |q: usize| samples[(samples.len() - 1) * q / 100];
+    (at(50), at(99), at(100))
+}
+
+/// Authenticate all of `uavs` concurrently for `rounds` rounds while probing the GS with a cheap RPC.
+///
+/// Logs the latency of the authentications and of the probe, which stays flat as long as the
+/// pairing work does not run on the GS runtime, plus how many requests the GS refused as busy.
+pub(crate) async fn load_test(client: &GsRpcClient, uavs: &[UavConfig], rounds: usize) -> anyhow::Result<()> {
+    let busy_before = client.get_batch_auth_metrics(context::current()).await?.busy;
+    let done = Arc::new(AtomicBool::new(false));
+
+    // own task, so the UAV-side work of the load does not delay the probe
+    let probe = tokio::spawn({
+        let client = client.clone();
+        let done = done.clone();
+        async move {
+            let mut latency = vec![];
+            while !done.load(Ordering::Relaxed) {
+                let t = Instant::now();
+                client.get_gs_pubkey(context::current()).await?;
+                latency.push(t.elapsed());
+                tokio::time::sleep(PROBE_INTERVAL).await;
+            }
+            Ok::<_, anyhow::Error>(latency)
+        }
+    });
+
+    let mut auth_latency = vec![];
+    let mut accepted = 0;
+    for round in 0..rounds {
+        let t = Instant::now();
+        let tasks = uavs
+            .iter()
+            .cloned()
+            .map(|uav| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let t = Instant::now();
+                    let ok = auth_uav(&client, &uav).await;
+                    (t.elapsed(), ok)
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            let (elapsed, ok) = task.await?;
+            auth_latency.push(elapsed);
+            accepted += ok? as usize;
+        }
+        info!("Load round {}: {} authentications in {:?}", round, uavs.len(), t.elapsed());
+    }
+    done.store(true, Ordering::Relaxed);
+    let mut probe_latency = probe.await??;
+    let busy = client.get_batch_auth_metrics(context::current()).await?.busy - busy_before;
+
+    let (p50, p99, max) = percentiles(&mut auth_latency);
+    info!(
+        "Load test: {}/{} authenticated, latency p50 {:?} p99 {:?} max {:?}, {} busy answers",
+        accepted,
+        auth_latency.len(),
+        p50,
+        p99,
+        max,
+        busy
+    );
+    let (p50, p99, max) = percentiles(&mut probe_latency);
+    info!(
+        "Load test probe: {} calls, latency p50 {:?} p99 {:?} max {:?}",
+        probe_latency.len(),
+        p50,
+        p99,
+        max
+    );
+    Ok(())
+}
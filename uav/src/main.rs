@@ -1,27 +1,40 @@
+mod agent;
 mod auth;
+mod channel;
 mod comm;
+#[cfg(feature = "fault-injection")]
+mod fault;
+mod group;
+mod keystore;
+mod load;
 mod mem;
-mod puf;
-// this module is for serial communication with PUF
-// mod puf_serial;
+mod puf_key;
 mod register;
 mod uav_cfg;
 use crate::{
-    auth::{auth, batch_auth},
-    comm::comm_with_uavs,
+    agent::{run_agent, AgentConfig},
+    auth::{auth, auth_trials, batch_auth, concurrent_auth},
+    channel::SecureClient,
+    comm::{batch_group, comm_with_uavs},
+    group::group_churn,
+    keystore::{Keystore, Unlock},
+    load::load_test,
 };
-use blstrs_plus::G1Affine;
+use blstrs_plus::{G1Affine, G2Affine};
 use clap::Parser;
 use dashmap::DashMap;
 use lazy_static::lazy_static;
-use puf::Puf;
+use puf::{MajorityPuf, PufBackend, PufSpec};
+use puf_key::PufKey;
 use register::register;
-use rpc::{GsRpcClient, TaRpcClient};
+use rpc::{GsRpcClient, TaRpcClient, TA_MAX_FRAME_LENGTH};
+use std::{sync::Arc, time::Duration};
 use tarpc::{client, context, tokio_serde::formats::Json};
 use tokio::sync::OnceCell;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 use uav_cfg::UavConfig;
+use utils::SecureChannel;
 
 #[global_allocator]
 static GLOBAL: mem::TrackingAllocator = mem::TrackingAllocator;
@@ -34,12 +47,79 @@ struct CliArgs {
     #[arg(short, long, help = "Register number", default_value = "10")]
     pub num: usize,
 
+    #[arg(long, help = "Register identities whose signing key is derived from the PUF")]
+    pub puf_key: bool,
+
     #[arg(short, long, help = "Number of authentication attempts", default_value = "1")]
     pub all_auth_num: usize,
 
+    #[arg(
+        long,
+        help = "Authenticate the keystore identities in turn this many times and report the share the GS accepts",
+        default_value = "0"
+    )]
+    pub auth_trials: usize,
+
     #[arg(short, long, help = "Batch authentication", default_value = "1")]
     pub batch_auth: Option<usize>,
 
+    #[cfg(feature = "fault-injection")]
+    #[arg(long, help = "Invalidate this many signatures in batch authentication", default_value = "0")]
+    pub corrupt_sigs: usize,
+
+    #[arg(long, help = "Authenticate the batch with concurrent single-UAV calls instead of one batch call")]
+    pub concurrent_auth: bool,
+
+    #[arg(long, help = "Load test rounds of concurrent authentication", default_value = "0")]
+    pub load_rounds: usize,
+
+    #[arg(long, help = "Telemetry frames sent over the secure channel after auth", default_value = "0")]
+    pub telemetry: usize,
+
+    #[arg(long, help = "Form a group of the batch-authenticated UAVs and distribute its key")]
+    pub group: bool,
+
+    #[arg(long, help = "Run named group create/join/leave/evict rekeying over the batch-authenticated UAVs")]
+    pub group_name: Option<String>,
+
+    #[arg(long, help = "Only accept ground stations certified for this region or fleet")]
+    pub gs_scope: Option<String>,
+
+    #[arg(long, help = "Encrypted identity keystore", default_value = "uav.keystore")]
+    pub keystore: std::path::PathBuf,
+
+    #[arg(long, help = "Keystore label of the identity for single authentication and agent mode")]
+    pub label: Option<String>,
+
+    #[arg(long, help = "Run as a long-lived agent that keeps the first UAV authenticated")]
+    pub daemon: bool,
+
+    #[arg(
+        long,
+        help = "Agent session key lifetime in seconds, at most the GS_SESSION_TTL of the GS",
+        default_value = "3600"
+    )]
+    pub session_ttl: u64,
+
+    #[arg(long, help = "Agent group key poll interval in seconds", default_value = "5")]
+    pub group_poll: u64,
+
+    #[arg(long, help = "CRT shard size of the GS, which bounds the frames it sends", default_value_t = rpc::DEFAULT_SHARD_SIZE)]
+    pub gs_shard_size: usize,
+
+    #[arg(long, help = "Agent control socket path", default_value = "uav.sock")]
+    pub control_socket: std::path::PathBuf,
+
+    #[arg(
+        long,
+        help = "PUF backend: tcp://host:port, serial:///dev/ttyUSB0?baud=115200 or sim:seed, each taking ?challenge_bits=&response_bits=",
+        default_value = "tcp://127.0.0.1:12345"
+    )]
+    pub puf: PufSpec,
+
+    #[arg(long, help = "PUF reads per challenge, majority-voted bitwise; odd", default_value = "1")]
+    pub puf_reads: usize,
+
     #[arg(long, help = "PUF TCP connection pool size", default_value = "8")]
     pub puf_pool_size: usize,
 
@@ -59,13 +139,19 @@ struct CliArgs {
 const TAG: &[u8] = b"BLS_SIG_BLS12381G1_XMD:BLAKE2b-512_SSWU_RO_NUL_";
 
 lazy_static! {
-    // static ref PUF: Puf = Puf::new(([127, 0, 0, 1], 12345));
     static ref UAV_AUTH_LIST: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(vec![]);
     static ref UAV_SESSION_KEYS: DashMap<String, String> = DashMap::new();
+    /// Secure channel of each session, kept across `SecureClient`s so sequence numbers never restart under one key.
+    static ref SECURE_CHANNELS: DashMap<String, SecureChannel> = DashMap::new();
+    static ref GS_PUBKEYS: DashMap<String, G2Affine> = DashMap::new();
+    /// Last accepted group key epoch per (uid, group id).
+    static ref GROUP_EPOCHS: DashMap<(String, String), u64> = DashMap::new();
 }
 static UAV_CONFIG: OnceCell<UavConfig> = OnceCell::const_new();
 static TA_PUBKEY1: OnceCell<G1Affine> = OnceCell::const_new();
-static PUF: OnceCell<Puf> = OnceCell::const_new();
+/// Scope a GS certificate must cover; unset accepts every scope.
+static GS_SCOPE: OnceCell<String> = OnceCell::const_new();
+static PUF: OnceCell<Arc<dyn PufBackend>> = OnceCell::const_new();
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -77,27 +163,39 @@ async fn main() -> anyhow::Result<()> {
     mem::log_checkpoint("startup");
     let args = CliArgs::parse();
     let puf_pool_size = args.puf_pool_size;
+    if let Some(scope) = &args.gs_scope {
+        GS_SCOPE.set(scope.clone()).expect("GS_SCOPE already set");
+    }
 
     let ta_addr = format!("{}:{}", args.ta_ip, args.ta_port);
     let gs_addr = format!("{}:{}", args.gs_ip, args.gs_port);
 
-    PUF.get_or_init(|| async {
-        let puf = Puf::new_with_pool_size(([127, 0, 0, 1], 12345), puf_pool_size)
-            .await
-            .expect("Failed to initialize PUF");
+    let puf = args.puf.connect(puf_pool_size).await.expect("Failed to initialize PUF");
+    let puf: Arc<dyn PufBackend> = if args.puf_reads > 1 {
+        Arc::new(MajorityPuf::new(puf, args.puf_reads)?)
+    } else {
         puf
-    })
-    .await;
+    };
+    info!("PUF geometry: {}", puf.geometry());
+    PUF.get_or_init(|| async { puf }).await;
     mem::log_checkpoint("puf_ready");
 
-    if args.register || !std::fs::exists("uav.json")? {
+    // locked by the passphrase in UAV_KEYSTORE_PASSPHRASE, or by the PUF
+    let unlock = Unlock::from_env();
+    let keystore = if args.register {
+        None
+    } else {
+        Keystore::open_or_migrate(&args.keystore, unlock.clone()).await?
+    };
+    let Some(keystore) = keystore else {
         let register_start = mem::reset_phase_peak();
         let mut transport = tarpc::serde_transport::tcp::connect(&ta_addr, Json::default);
-        transport.config_mut().max_frame_length(usize::MAX);
+        transport.config_mut().max_frame_length(TA_MAX_FRAME_LENGTH);
         let client = TaRpcClient::new(client::Config::default(), transport.await?).spawn();
         info!("Connected to TA at {}", ta_addr);
 
-        let results: Vec<Result<UavConfig, anyhow::Error>> = futures::future::join_all((0..args.num).map(|_| call_register(&client))).await;
+        let results: Vec<Result<(UavConfig, Option<PufKey>), anyhow::Error>> =
+            futures::future::join_all((0..args.num).map(|_| call_register(&client, args.puf_key))).await;
         let mut good_cfgs = Vec::new();
         for res in results {
             match res {
@@ -107,33 +205,57 @@ async fn main() -> anyhow::Result<()> {
         }
 
         // save the register uav configs
-        serde_json::to_writer(std::fs::File::create("uav.json")?, &good_cfgs)?;
+        let mut keystore = Keystore::create(&args.keystore, unlock);
+        keystore.replace(good_cfgs.clone());
+        keystore.save().await?;
+        info!("Saved {} identities to {}", good_cfgs.len(), args.keystore.display());
 
         let uav_cfg = good_cfgs
             .last()
-            .cloned()
+            .map(|(cfg, _)| cfg.clone())
             .ok_or_else(|| anyhow::anyhow!("no successful UAV configs"))?;
 
         UAV_CONFIG.set(uav_cfg.clone()).ok();
         tracing::debug!("uav: {:?}", UAV_CONFIG.get());
         mem::log_phase("register", register_start);
         return Ok(());
+    };
+    info!(
+        "Unlocked {} identities from {}, {} with PUF-derived keys",
+        keystore.entries().len(),
+        args.keystore.display(),
+        keystore.entries().iter().filter(|e| e.puf_key.is_some()).count()
+    );
+    let uavs = keystore.uavs();
+    // identity of single authentication and agent mode
+    let uav = match &args.label {
+        Some(label) => keystore.get(label).ok_or(anyhow::anyhow!("No identity labelled {label}"))?,
+        None => uavs.first().ok_or(anyhow::anyhow!("No identity in the keystore"))?,
     }
-
-    // read uav config from file
-    let f = std::fs::File::open("uav.json").expect("not found uav.json, please register.");
-    let uavs = serde_json::from_reader::<_, Vec<UavConfig>>(f)?;
+    .clone();
 
     let mut ta_transport = tarpc::serde_transport::tcp::connect(&ta_addr, Json::default);
-    ta_transport.config_mut().max_frame_length(usize::MAX);
+    ta_transport.config_mut().max_frame_length(TA_MAX_FRAME_LENGTH);
     let ta_client = TaRpcClient::new(client::Config::default(), ta_transport.await?).spawn();
     info!("Connected to TA at {}", &ta_addr);
     let ta_pk1 = ta_client.get_ta_pubkey1(context::current()).await?;
     let ta_pk1 = G1Affine::from_compressed_hex(&ta_pk1).expect("Invalid trust authority public key");
     TA_PUBKEY1.set(ta_pk1).expect("TA_PUBKEY1 already set");
 
+    if args.daemon {
+        UAV_CONFIG.set(uav.clone()).expect("UAV_CONFIG already set");
+        let cfg = AgentConfig {
+            gs_addr,
+            session_ttl: Duration::from_secs(args.session_ttl),
+            group_poll: Duration::from_secs(args.group_poll),
+            max_frame_length: rpc::max_frame_length(args.gs_shard_size),
+            control_socket: args.control_socket,
+        };
+        return run_agent(&ta_client, uav, cfg).await;
+    }
+
     let mut transport = tarpc::serde_transport::tcp::connect(&gs_addr, Json::default);
-    transport.config_mut().max_frame_length(usize::MAX);
+    transport.config_mut().max_frame_length(rpc::max_frame_length(args.gs_shard_size));
 
     let client = GsRpcClient::new(client::Config::default(), transport.await?).spawn();
     info!("Connected to GS at {}", &gs_addr);
@@ -144,18 +266,58 @@ async fn main() -> anyhow::Result<()> {
             if uavs.len() != num {
                 return Err(anyhow::anyhow!("Not enough UAVs for batch authentication"));
             }
+            if args.load_rounds > 0 {
+                return load_test(&client, &uavs, args.load_rounds).await;
+            }
+            #[cfg(feature = "fault-injection")]
+            fault::corrupt(&uavs[..args.corrupt_sigs.min(num)]);
             info!("Batch authentication with {} UAVs", uavs.len());
             let auth_start = mem::reset_phase_peak();
             let t = std::time::Instant::now();
-            batch_auth(&client, uavs).await?;
+            let uids = if args.concurrent_auth {
+                concurrent_auth(&client, uavs).await?
+            } else {
+                batch_auth(&client, uavs).await?
+            };
             info!("Batch authentication time elapsed: {:?}", t.elapsed());
             mem::log_phase("batch_auth", auth_start);
+            let metrics = client.get_batch_auth_metrics(context::current()).await?;
+            info!(
+                "GS batch auth metrics: {} batches, {} fallbacks, {} sub-checks, {}/{} requests rejected, {} busy",
+                metrics.batches, metrics.fallbacks, metrics.sub_checks, metrics.rejected, metrics.requests, metrics.busy
+            );
+
+            if args.group {
+                let comm_start = mem::reset_phase_peak();
+                let t = std::time::Instant::now();
+                batch_group(&client, &ta_client, &uids).await?;
+                info!("Batch group key distribution time elapsed: {:?}", t.elapsed());
+                mem::log_phase("batch_group_comm", comm_start);
+            }
+            if let Some(name) = &args.group_name {
+                let comm_start = mem::reset_phase_peak();
+                let t = std::time::Instant::now();
+                group_churn(&client, &ta_client, &uids, name).await?;
+                info!("Group membership churn time elapsed: {:?}", t.elapsed());
+                mem::log_phase("group_churn", comm_start);
+            }
             return Ok(());
         }
         _ => {}
     }
 
-    let uav = uavs.first().ok_or(anyhow::anyhow!("No UAV config found in uav.json"))?.clone();
+    if args.auth_trials > 0 {
+        let accepted = auth_trials(&client, &uavs, args.auth_trials).await?;
+        info!(
+            "{} PUF reads per challenge: {}/{} authentications accepted ({:.1}%)",
+            args.puf_reads,
+            accepted,
+            args.auth_trials,
+            100.0 * accepted as f64 / args.auth_trials as f64
+        );
+        return Ok(());
+    }
+
     UAV_CONFIG.set(uav).expect("UAV_CONFIG already set");
 
     let auth_start = mem::reset_phase_peak();
@@ -168,6 +330,17 @@ async fn main() -> anyhow::Result<()> {
     info!("Auth {} time elapsed: {:?}", args.all_auth_num, t.elapsed());
     mem::log_phase("auth", auth_start);
 
+    if args.telemetry > 0 {
+        let mut channel = SecureClient::open(&client, &UAV_CONFIG.get().unwrap().uid)?;
+        let t = std::time::Instant::now();
+        for _ in 0..args.telemetry {
+            channel.send(&chrono::Utc::now().timestamp_millis().to_be_bytes()).await?;
+        }
+        let acks = channel.poll().await?;
+        info!("Secure channel sent {} frames, received {} acks", args.telemetry, acks.len());
+        info!("Secure channel time elapsed: {:?}", t.elapsed());
+    }
+
     // parallel optimization
     // let t = std::time::Instant::now();
     // futures::future::join_all((0..args.all_auth_num).map(|_| call_auth(&client))).await;
@@ -181,16 +354,16 @@ async fn main() -> anyhow::Result<()> {
 
     // communicate with other uavs
     let t = std::time::Instant::now();
-    comm_with_uavs(&client).await?;
+    comm_with_uavs(&client, &ta_client).await?;
     info!("Communicate group size: {}", ids.len());
     info!("Communicate group key time elapsed: {:?}", t.elapsed());
     mem::log_phase("group_comm", comm_start);
     Ok(())
 }
 
-async fn call_register(client: &TaRpcClient) -> anyhow::Result<UavConfig> {
+async fn call_register(client: &TaRpcClient, puf_key: bool) -> anyhow::Result<(UavConfig, Option<PufKey>)> {
     // Register multiple UAVs and use the last config
-    let cfg = register(client).await?;
+    let cfg = register(client, puf_key).await?;
     Ok(cfg)
 }
 
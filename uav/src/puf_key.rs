@@ -0,0 +1,82 @@
+use crate::PUF;
+use blake2::{Blake2b512, Digest};
+use blstrs_plus::Scalar;
+use puf::PufGeometry;
+use rand::{Rng, RngCore};
+use utils::{fuzzy_commit, fuzzy_recover, fuzzy_response_len};
+
+/// Bytes of the secret the signing key is derived from.
+const SECRET_SIZE: usize = 16;
+
+/// Public enrollment data of a signing key derived from the PUF.
+///
+/// The responses to `challenges` are concatenated and `helper` binds a random secret to them, so
+/// the same secret, and from it `sk`, comes back from a noisy re-reading. Neither field reveals
+/// the secret without the PUF.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct PufKey {
+    pub challenges: Vec<String>,
+    pub helper: String,
+    /// Geometry of the PUF at enrollment.
+    pub geometry: PufGeometry,
+}
+
+impl PufKey {
+    /// Enroll a fresh key on fresh challenges.
+    pub(crate) async fn enroll() -> anyhow::Result<(Self, Scalar)> {
+        let (key, secret) = Self::enroll_secret().await?;
+        Ok((key, secret_to_sk(&secret)))
+    }
+
+    /// Re-derive `sk` from the PUF.
+    pub(crate) async fn derive(&self) -> anyhow::Result<Scalar> {
+        Ok(secret_to_sk(&self.recover_secret().await?))
+    }
+
+    /// Bind a fresh random secret to the responses to fresh challenges.
+    pub(crate) async fn enroll_secret() -> anyhow::Result<(Self, [u8; SECRET_SIZE])> {
+        let geometry = PUF.get().expect("PUF not initialized").geometry();
+        let blocks = fuzzy_response_len(SECRET_SIZE).div_ceil(geometry.response_bytes());
+        let challenges = (0..blocks)
+            .map(|_| {
+                let mut c = vec![0u8; geometry.challenge_bytes()];
+                rand::thread_rng().fill_bytes(&mut c);
+                hex::encode(c)
+            })
+            .collect::<Vec<_>>();
+        let secret = rand::thread_rng().gen::<[u8; SECRET_SIZE]>();
+        let helper = fuzzy_commit(&secret, &read_responses(&geometry, &challenges).await?)?;
+        let key = Self {
+            challenges,
+            helper: hex::encode(helper),
+            geometry,
+        };
+        Ok((key, secret))
+    }
+
+    /// The secret bound at enrollment, recovered from a fresh reading of the PUF.
+    pub(crate) async fn recover_secret(&self) -> anyhow::Result<Vec<u8>> {
+        let response = read_responses(&self.geometry, &self.challenges).await?;
+        fuzzy_recover(&hex::decode(&self.helper)?, &response, SECRET_SIZE)
+    }
+}
+
+/// Concatenated responses to `challenges` from a PUF of `geometry`.
+async fn read_responses(geometry: &PufGeometry, challenges: &[String]) -> anyhow::Result<Vec<u8>> {
+    let puf = PUF.get().expect("PUF not initialized");
+    if puf.geometry() != *geometry {
+        anyhow::bail!("PUF answers with {}, the key was enrolled with {}", puf.geometry(), geometry);
+    }
+    let mut response = Vec::with_capacity(challenges.len() * geometry.response_bytes());
+    for c in challenges {
+        response.extend(geometry.decode_response(&puf.calculate(c).await?)?);
+    }
+    Ok(response)
+}
+
+fn secret_to_sk(secret: &[u8]) -> Scalar {
+    let mut hasher = Blake2b512::new();
+    hasher.update(b"uav-puf-sk");
+    hasher.update(secret);
+    Scalar::from_bytes_wide(&hasher.finalize().into())
+}
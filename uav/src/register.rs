@@ -1,15 +1,35 @@
-use crate::{UavConfig, PUF};
-use blstrs_plus::{G2Affine, Scalar};
-use rpc::{TaRpcClient, UavRegisterRequest1};
+use crate::{puf_key::PufKey, UavConfig, PUF, TAG};
+use blake2::Blake2b512;
+use blstrs_plus::{elliptic_curve::hash2curve::ExpandMsgXmd, group::prime::PrimeCurveAffine, G1Projective, G2Affine, Scalar};
+use hex::ToHex;
+use rpc::{TaRpcClient, UavPublicKey, UavRegisterRequest1};
 use tarpc::context;
 use tracing::{debug, info};
 use utils::abbreviate_key_default;
 
-pub(crate) async fn register(client: &TaRpcClient) -> anyhow::Result<UavConfig> {
+/// Register a new identity with the TA.
+///
+/// With `puf_key` the signing key is derived from the PUF and only `pk` reaches the TA; the
+/// returned [`PufKey`] holds the public data to derive it again.
+pub(crate) async fn register(client: &TaRpcClient, puf_key: bool) -> anyhow::Result<(UavConfig, Option<PufKey>)> {
     let ctx = context::current();
 
+    let own_key = if puf_key { Some(PufKey::enroll().await?) } else { None };
+    let key = own_key.as_ref().map(|(_, sk)| {
+        let pk = (G2Affine::generator() * sk).to_compressed().encode_hex::<String>();
+        let h = G1Projective::hash::<ExpandMsgXmd<Blake2b512>>(&UavPublicKey::pop_bytes(&pk), TAG);
+        let pop = (h * sk).to_compressed().encode_hex::<String>();
+        UavPublicKey { pk, pop }
+    });
+
     let resp1 = client
-        .register_uav_phase1(ctx, UavRegisterRequest1 {})
+        .register_uav_phase1(
+            ctx,
+            UavRegisterRequest1 {
+                key,
+                geometry: PUF.get().unwrap().geometry(),
+            },
+        )
         .await?
         .ok_or_else(|| anyhow::anyhow!("UAV registration phase 1 failed"))?;
     debug!("UAV registration phase 1 completed: {:?}", resp1);
@@ -17,11 +37,15 @@ pub(crate) async fn register(client: &TaRpcClient) -> anyhow::Result<UavConfig>
     let puf_response = PUF
         .get()
         .unwrap()
-        .calculate(resp1.puf_challenge)
+        .calculate(&resp1.puf_challenge)
         .await
         .map_err(|e| anyhow::anyhow!("PUF calculation failed of {}", e))?;
 
-    let sk = Scalar::from_be_hex(&resp1.uav_sk).expect("Invalid UAV secret key format");
+    let (sk, puf_key) = match (own_key, &resp1.uav_sk) {
+        (Some((puf_key, sk)), None) => (sk, Some(puf_key)),
+        (None, Some(sk)) => (Scalar::from_be_hex(sk).expect("Invalid UAV secret key format"), None),
+        _ => anyhow::bail!("TA answered with an unexpected key mode"),
+    };
     let pk = G2Affine::from_compressed_hex(&resp1.uav_pubkey).expect("Invalid UAV public key format");
 
     let cfg = UavConfig::new(resp1.uid.clone(), sk, pk);
@@ -35,5 +59,5 @@ pub(crate) async fn register(client: &TaRpcClient) -> anyhow::Result<UavConfig>
         .await?
         .ok_or_else(|| anyhow::anyhow!("UAV registration phase 2 failed"))?;
     info!("UAV registered with uid: {}", abbreviate_key_default(&resp1.uid));
-    Ok(cfg)
+    Ok((cfg, puf_key))
 }
@@ -0,0 +1,40 @@
+use std::hint::black_box;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use rug::Integer;
+use utils::{CrtGroup, build_crt, hash_to_prime};
+
+fn primes(n: usize) -> Vec<Integer> {
+    (0..n).map(|i| hash_to_prime(format!("crt-bench-{i}"))).collect()
+}
+
+fn crt_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crt");
+    group.sample_size(10);
+
+    for n in [1000usize, 2500, 5000, 10000] {
+        let p = primes(n + 1);
+        let extra = p[n].clone();
+        let base = CrtGroup::new(p[..n].to_vec()).expect("distinct primes are coprime");
+
+        // full rebuild after one member joins
+        group.bench_with_input(BenchmarkId::new("build_crt", n), &p, |b, p| {
+            b.iter(|| build_crt(black_box(p.clone())));
+        });
+        group.bench_with_input(BenchmarkId::new("crt_group_add", n), &extra, |b, extra| {
+            b.iter_batched(
+                || base.clone(),
+                |mut crt| crt.add(black_box(extra.clone())).unwrap(),
+                BatchSize::LargeInput,
+            );
+        });
+        group.bench_with_input(BenchmarkId::new("crt_group_remove", n), &p[n / 2], |b, leaver| {
+            b.iter_batched(|| base.clone(), |mut crt| crt.remove(black_box(leaver)), BatchSize::LargeInput);
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, crt_bench);
+criterion_main!(benches);
@@ -0,0 +1,87 @@
+use std::hint::black_box;
+
+use blstrs_plus::{
+    G1Affine, G1Projective, G2Affine, G2Prepared, Scalar,
+    ff::Field,
+    group::{Group, prime::PrimeCurveAffine},
+    pairing,
+};
+use criterion::{Criterion, criterion_group, criterion_main};
+use rand_core::OsRng;
+use utils::{g1_mul_generator, g2_generator_prepared, pairing_product_is_identity};
+
+fn random_scalar() -> Scalar {
+    Scalar::random(OsRng)
+}
+
+/// A valid phase 2 equation `e(sigma + z, g2) = e(h, pk_u) · e(g_r, pk_t)`.
+struct Phase2 {
+    sigma_z: G1Affine,
+    h: G1Affine,
+    g_r: G1Affine,
+    pk_u: G2Affine,
+    pk_t: G2Affine,
+}
+
+fn phase2() -> Phase2 {
+    let (sk_u, sk_t) = (random_scalar(), random_scalar());
+    let h = G1Affine::from(G1Projective::generator() * random_scalar());
+    let g_r = G1Affine::from(G1Projective::generator() * random_scalar());
+    Phase2 {
+        sigma_z: (h * sk_u + g_r * sk_t).into(),
+        h,
+        g_r,
+        pk_u: (G2Affine::generator() * sk_u).into(),
+        pk_t: (G2Affine::generator() * sk_t).into(),
+    }
+}
+
+fn pairing_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("phase2_check");
+    let eq = phase2();
+    let (pk_u, pk_t) = (G2Prepared::from(eq.pk_u), G2Prepared::from(eq.pk_t));
+    let neg_sigma_z = -eq.sigma_z;
+
+    // before: three pairings and final exponentiations
+    group.bench_function("three_pairings", |b| {
+        b.iter(|| {
+            let lhs = pairing(black_box(&eq.sigma_z), &G2Affine::generator());
+            lhs == pairing(black_box(&eq.h), &eq.pk_u) * pairing(black_box(&eq.g_r), &eq.pk_t)
+        });
+    });
+    // one multi-pairing, preparing every G2 point per check
+    group.bench_function("multi_pairing_uncached", |b| {
+        b.iter(|| {
+            let (g2, pk_u, pk_t) = (
+                G2Prepared::from(G2Affine::generator()),
+                G2Prepared::from(eq.pk_u),
+                G2Prepared::from(eq.pk_t),
+            );
+            pairing_product_is_identity(&[
+                (black_box(&neg_sigma_z), &g2),
+                (black_box(&eq.h), &pk_u),
+                (black_box(&eq.g_r), &pk_t),
+            ])
+        });
+    });
+    // after: one multi-pairing over cached G2Prepared
+    group.bench_function("multi_pairing_cached", |b| {
+        b.iter(|| {
+            pairing_product_is_identity(&[
+                (black_box(&neg_sigma_z), g2_generator_prepared()),
+                (black_box(&eq.h), &pk_u),
+                (black_box(&eq.g_r), &pk_t),
+            ])
+        });
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("g1_generator_mul");
+    let k = random_scalar();
+    group.bench_function("plain", |b| b.iter(|| G1Affine::generator() * black_box(k)));
+    group.bench_function("fixed_base_table", |b| b.iter(|| g1_mul_generator(black_box(&k))));
+    group.finish();
+}
+
+criterion_group!(benches, pairing_bench);
+criterion_main!(benches);
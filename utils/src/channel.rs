@@ -0,0 +1,162 @@
+use aes_gcm::{
+    Aes128Gcm, Nonce,
+    aead::{Aead, KeyInit, Payload},
+};
+use blake2::{Blake2b512, Digest};
+
+/// Direction of a frame on the UAV <-> GS secure channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    UavToGs,
+    GsToUav,
+}
+
+impl Direction {
+    fn label(self) -> &'static [u8] {
+        match self {
+            Direction::UavToGs => b"egcda/uav->gs",
+            Direction::GsToUav => b"egcda/gs->uav",
+        }
+    }
+
+    fn reverse(self) -> Self {
+        match self {
+            Direction::UavToGs => Direction::GsToUav,
+            Direction::GsToUav => Direction::UavToGs,
+        }
+    }
+}
+
+/// Derive the AES-128 key of one direction from the session key `ssk_g_u`.
+pub fn derive_direction_key(session_key: &[u8; 16], uid: &str, direction: Direction) -> [u8; 16] {
+    let mut hasher = Blake2b512::new();
+    hasher.update(direction.label());
+    hasher.update(uid.as_bytes());
+    hasher.update(session_key);
+    let digest = hasher.finalize();
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&digest[..16]);
+    key
+}
+
+/// AES-128-GCM channel with per-direction keys and sequence-number nonces.
+///
+/// Sequence numbers of received frames must be strictly increasing, so replayed
+/// and reordered frames are rejected.
+#[derive(Debug, Clone)]
+pub struct SecureChannel {
+    uid: String,
+    local: Direction,
+    send_key: [u8; 16],
+    recv_key: [u8; 16],
+    next_send: u64,
+    last_recv: Option<u64>,
+}
+
+impl SecureChannel {
+    /// Create the channel endpoint which sends in the `local` direction.
+    pub fn new(uid: impl Into<String>, session_key: &[u8; 16], local: Direction) -> Self {
+        let uid = uid.into();
+        Self {
+            send_key: derive_direction_key(session_key, &uid, local),
+            recv_key: derive_direction_key(session_key, &uid, local.reverse()),
+            uid,
+            local,
+            next_send: 0,
+            last_recv: None,
+        }
+    }
+
+    pub fn uid(&self) -> &str {
+        &self.uid
+    }
+
+    /// Encrypt `plaintext` under the next sequence number.
+    pub fn seal(&mut self, plaintext: &[u8]) -> anyhow::Result<(u64, Vec<u8>)> {
+        let seq = self.next_send;
+        let next = seq.checked_add(1).ok_or_else(|| anyhow::anyhow!("sequence number exhausted"))?;
+        let aad = self.aad(self.local, seq);
+        let cipher = Aes128Gcm::new_from_slice(&self.send_key)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&seq_nonce(seq)), Payload { msg: plaintext, aad: &aad })
+            .map_err(|e| anyhow::anyhow!("AES-GCM encryption failed: {:?}", e))?;
+        self.next_send = next;
+        Ok((seq, ciphertext))
+    }
+
+    /// Decrypt a frame from the peer, rejecting replayed or out-of-order sequence numbers.
+    pub fn open(&mut self, seq: u64, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if self.last_recv.is_some_and(|last| seq <= last) {
+            anyhow::bail!("replayed or out-of-order frame: seq {seq}");
+        }
+        let aad = self.aad(self.local.reverse(), seq);
+        let cipher = Aes128Gcm::new_from_slice(&self.recv_key)?;
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&seq_nonce(seq)),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("AES-GCM decryption failed: {:?}", e))?;
+        self.last_recv = Some(seq);
+        Ok(plaintext)
+    }
+
+    fn aad(&self, direction: Direction, seq: u64) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(direction.label().len() + self.uid.len() + 8);
+        aad.extend_from_slice(direction.label());
+        aad.extend_from_slice(self.uid.as_bytes());
+        aad.extend_from_slice(&seq.to_be_bytes());
+        aad
+    }
+}
+
+fn seq_nonce(seq: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&seq.to_be_bytes());
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> (SecureChannel, SecureChannel) {
+        let key = [7u8; 16];
+        (
+            SecureChannel::new("uav-1", &key, Direction::UavToGs),
+            SecureChannel::new("uav-1", &key, Direction::GsToUav),
+        )
+    }
+
+    #[test]
+    fn test_channel_roundtrip() {
+        let (mut uav, mut gs) = pair();
+        let (seq, ct) = uav.seal(b"telemetry").unwrap();
+        assert_eq!(gs.open(seq, &ct).unwrap(), b"telemetry");
+
+        let (seq, ct) = gs.seal(b"command").unwrap();
+        assert_eq!(uav.open(seq, &ct).unwrap(), b"command");
+    }
+
+    #[test]
+    fn test_channel_rejects_replay_and_reorder() {
+        let (mut uav, mut gs) = pair();
+        let (s0, c0) = uav.seal(b"a").unwrap();
+        let (s1, c1) = uav.seal(b"b").unwrap();
+
+        gs.open(s1, &c1).unwrap();
+        assert!(gs.open(s0, &c0).is_err());
+        assert!(gs.open(s1, &c1).is_err());
+    }
+
+    #[test]
+    fn test_channel_rejects_reflection() {
+        let (mut uav, _) = pair();
+        let (mut other_uav, _) = pair();
+        let (seq, ct) = uav.seal(b"a").unwrap();
+        assert!(other_uav.open(seq, &ct).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use rayon::prelude::*;
+use rug::Integer;
+use std::str::FromStr;
+
+/// Construction used to compute `eta` for a one-shot group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrtBackend {
+    /// [`crate::build_crt`]: divide the full product by every modulus, quadratic in `n`.
+    #[default]
+    Direct,
+    /// [`build_crt_tree`]: subproduct and remainder trees, quasi-linear in `n`.
+    Tree,
+}
+
+impl CrtBackend {
+    pub fn build(self, p: Vec<Integer>) -> Integer {
+        match self {
+            CrtBackend::Direct => crate::build_crt(p),
+            CrtBackend::Tree => build_crt_tree(p),
+        }
+    }
+}
+
+impl FromStr for CrtBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "direct" => Ok(CrtBackend::Direct),
+            "tree" => Ok(CrtBackend::Tree),
+            _ => anyhow::bail!("Unknown CRT backend: {s} (expected direct or tree)"),
+        }
+    }
+}
+
+/// Subproduct tree of `p`: level 0 holds the moduli and every following level the pairwise
+/// products of the previous one, up to the single root `M`.
+fn product_tree(p: Vec<Integer>) -> Vec<Vec<Integer>> {
+    let mut levels = vec![p];
+    while levels.last().unwrap().len() > 1 {
+        let next = levels
+            .last()
+            .unwrap()
+            .par_chunks(2)
+            .map(|pair| pair.iter().product::<Integer>())
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+/// Same value as [`crate::build_crt`], `sum (M / p_i) * ((M / p_i)^-1 mod p_i)`, in quasi-linear time.
+///
+/// A remainder tree reduces `M` modulo every `p_i^2`, which yields `(M / p_i) mod p_i` without
+/// ever dividing `M` by `p_i`. The weighted sum is then folded back up the product tree.
+pub fn build_crt_tree(p: Vec<Integer>) -> Integer {
+    if p.is_empty() {
+        return Integer::new();
+    }
+    let tree = product_tree(p);
+
+    // M mod node^2 for every node, from the root down to the leaves
+    let mut rems = vec![tree.last().unwrap()[0].clone()];
+    for level in tree.iter().rev().skip(1) {
+        rems = level
+            .par_iter()
+            .enumerate()
+            .map(|(j, node)| &rems[j / 2] % Integer::from(node.square_ref()))
+            .collect();
+    }
+
+    let mut vals = rems
+        .into_par_iter()
+        .zip(&tree[0])
+        .map(|(r_i, p_i)| {
+            let m_i = r_i.div_exact(p_i);
+            m_i.invert(p_i).expect("Failed to invert")
+        })
+        .collect::<Vec<_>>();
+
+    // fold sum c_i * (M / p_i) upwards: a parent combines its children as l * R + r * L
+    for level in &tree[..tree.len() - 1] {
+        vals = vals
+            .par_chunks(2)
+            .zip(level.par_chunks(2))
+            .map(|(v, n)| match (v, n) {
+                ([l, r], [n_l, n_r]) => Integer::from(l * n_r) + Integer::from(r * n_l),
+                _ => v[0].clone(),
+            })
+            .collect();
+    }
+    vals.pop().unwrap()
+}
+
+/// CRT state of a group of pairwise coprime moduli, maintained across membership changes.
+///
+/// Caches the product `M`, the basis coefficients `c_i = (M / p_i)^-1 mod p_i` and
+/// `eta ≡ 1 (mod p_i)` for every `p_i`, reduced modulo `M`. Adding or removing a modulus
+/// updates all three in `O(n)` small operations plus one multiplication by `M`, instead of
+/// recomputing every `M / p_i` and its inverse as [`crate::build_crt`] does.
+#[derive(Debug, Clone)]
+pub struct CrtGroup {
+    moduli: Vec<Integer>,
+    coefficients: Vec<Integer>,
+    m: Integer,
+    eta: Integer,
+}
+
+impl Default for CrtGroup {
+    fn default() -> Self {
+        Self {
+            moduli: vec![],
+            coefficients: vec![],
+            m: Integer::from(1),
+            eta: Integer::new(),
+        }
+    }
+}
+
+impl CrtGroup {
+    /// Build the CRT state of `p` from scratch.
+    pub fn new(p: Vec<Integer>) -> anyhow::Result<Self> {
+        let m = p.par_iter().product::<Integer>();
+        let (mi, coefficients): (Vec<_>, Vec<_>) = p
+            .par_iter()
+            .map(|p_i| {
+                let m_i = Integer::from(m.div_exact_ref(p_i));
+                let c_i = Integer::from(&m_i % p_i)
+                    .invert(p_i)
+                    .map_err(|_| anyhow::anyhow!("Moduli are not pairwise coprime"))?;
+                Ok((m_i, c_i))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let eta = mi
+            .par_iter()
+            .zip(&coefficients)
+            .map(|(m_i, c_i)| Integer::from(m_i * c_i))
+            .sum::<Integer>()
+            % &m;
+
+        Ok(Self {
+            moduli: p,
+            coefficients,
+            m,
+            eta,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.moduli.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moduli.is_empty()
+    }
+
+    pub fn moduli(&self) -> &[Integer] {
+        &self.moduli
+    }
+
+    pub fn contains(&self, p: &Integer) -> bool {
+        self.moduli.contains(p)
+    }
+
+    /// Product of all moduli.
+    pub fn modulus(&self) -> &Integer {
+        &self.m
+    }
+
+    /// The value congruent to 1 modulo every modulus, in `[0, M)`.
+    pub fn eta(&self) -> &Integer {
+        &self.eta
+    }
+
+    /// Add modulus `p`, which must be coprime to every modulus already in the group.
+    pub fn add(&mut self, p: Integer) -> anyhow::Result<()> {
+        let m_inv = Integer::from(&self.m % &p)
+            .invert(&p)
+            .map_err(|_| anyhow::anyhow!("Modulus is not coprime to the group"))?;
+        let p_inv = self
+            .moduli
+            .par_iter()
+            .map(|p_i| Integer::from(&p % p_i).invert(p_i).expect("coprime moduli are invertible"))
+            .collect::<Vec<_>>();
+
+        // eta' = eta + M * ((1 - eta) * M^-1 mod p) keeps eta' ≡ eta (mod M) and makes it ≡ 1 (mod p)
+        let t = (Integer::from(1) - &self.eta) * &m_inv;
+        let t = t.modulo(&p);
+        self.eta += Integer::from(&self.m * &t);
+
+        self.coefficients
+            .par_iter_mut()
+            .zip(p_inv.par_iter().zip(&self.moduli))
+            .for_each(|(c_i, (p_inv, p_i))| {
+                *c_i *= p_inv;
+                *c_i %= p_i;
+            });
+        self.coefficients.push(m_inv);
+        self.m *= &p;
+        self.moduli.push(p);
+        Ok(())
+    }
+
+    /// Remove modulus `p`; returns `false` if it is not part of the group.
+    pub fn remove(&mut self, p: &Integer) -> bool {
+        let Some(idx) = self.moduli.iter().position(|p_i| p_i == p) else {
+            return false;
+        };
+        self.moduli.swap_remove(idx);
+        self.coefficients.swap_remove(idx);
+
+        self.m.div_exact_mut(p);
+        self.eta %= &self.m;
+        self.coefficients.par_iter_mut().zip(&self.moduli).for_each(|(c_i, p_i)| {
+            *c_i *= p;
+            *c_i %= p_i;
+        });
+        true
+    }
+
+    /// The value in `[0, M)` congruent to `residues[i]` modulo the `i`-th modulus.
+    pub fn combine(&self, residues: &[Integer]) -> Integer {
+        assert_eq!(residues.len(), self.moduli.len(), "one residue per modulus");
+        self.moduli
+            .par_iter()
+            .zip(&self.coefficients)
+            .zip(residues)
+            .map(|((p_i, c_i), r_i)| Integer::from(self.m.div_exact_ref(p_i)) * (Integer::from(r_i * c_i) % p_i))
+            .sum::<Integer>()
+            % &self.m
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_crt;
+    use rug::integer::Order;
+
+    fn primes(n: usize) -> Vec<Integer> {
+        (0..n)
+            .map(|_| Integer::from_digits(&rand::random::<[u8; 16]>(), Order::MsfBe).next_prime())
+            .collect()
+    }
+
+    fn check(group: &CrtGroup) {
+        assert_eq!(group.modulus(), &group.moduli().iter().product::<Integer>());
+        for p in group.moduli() {
+            assert_eq!(Integer::from(group.eta() % p), 1);
+        }
+        assert_eq!(group.combine(&vec![Integer::from(1); group.len()]), *group.eta());
+    }
+
+    #[test]
+    fn test_crt_group_matches_build_crt() {
+        let p = primes(16);
+        let group = CrtGroup::new(p.clone()).unwrap();
+        check(&group);
+        assert_eq!(build_crt(p) % group.modulus(), *group.eta());
+    }
+
+    #[test]
+    fn test_crt_tree_matches_build_crt() {
+        for n in [1, 2, 3, 7, 16, 33] {
+            let p = primes(n);
+            assert_eq!(build_crt_tree(p.clone()), build_crt(p));
+        }
+        assert_eq!(build_crt_tree(vec![]), 0);
+    }
+
+    #[test]
+    fn test_crt_group_incremental() {
+        let p = primes(12);
+        let mut group = CrtGroup::default();
+        for p_i in &p[..8] {
+            group.add(p_i.clone()).unwrap();
+            check(&group);
+        }
+        assert!(group.add(p[0].clone()).is_err());
+
+        assert!(group.remove(&p[3]));
+        assert!(!group.remove(&p[3]));
+        check(&group);
+        for p_i in &p[8..] {
+            group.add(p_i.clone()).unwrap();
+        }
+        check(&group);
+
+        let rebuilt = CrtGroup::new(group.moduli().to_vec()).unwrap();
+        assert_eq!(rebuilt.eta(), group.eta());
+    }
+}
@@ -0,0 +1,81 @@
+/// Response bits spent on every secret bit; a majority vote corrects up to `(REPETITION - 1) / 2` flips.
+pub const REPETITION: usize = 5;
+
+/// Response bytes needed to bind a `secret_len`-byte secret.
+pub fn fuzzy_response_len(secret_len: usize) -> usize {
+    (secret_len * 8 * REPETITION).div_ceil(8)
+}
+
+fn bit(bytes: &[u8], i: usize) -> u8 {
+    (bytes[i / 8] >> (i % 8)) & 1
+}
+
+/// Helper data of a code-offset fuzzy extractor: `response` XOR the repetition encoding of `secret`.
+///
+/// The helper data is public; together with a noisy re-reading of the same response it gives back
+/// `secret` through [`fuzzy_recover`].
+pub fn fuzzy_commit(secret: &[u8], response: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let len = fuzzy_response_len(secret.len());
+    if response.len() < len {
+        anyhow::bail!("{} response bytes needed, got {}", len, response.len());
+    }
+    let mut helper = response[..len].to_vec();
+    for i in 0..secret.len() * 8 {
+        for j in 0..REPETITION {
+            let k = i * REPETITION + j;
+            helper[k / 8] ^= bit(secret, i) << (k % 8);
+        }
+    }
+    Ok(helper)
+}
+
+/// Recover the `secret_len`-byte secret bound by `helper` from a re-reading of the response.
+pub fn fuzzy_recover(helper: &[u8], response: &[u8], secret_len: usize) -> anyhow::Result<Vec<u8>> {
+    let len = fuzzy_response_len(secret_len);
+    if helper.len() != len || response.len() < len {
+        anyhow::bail!(
+            "{} helper and response bytes needed, got {} and {}",
+            len,
+            helper.len(),
+            response.len()
+        );
+    }
+    let mut secret = vec![0u8; secret_len];
+    for i in 0..secret_len * 8 {
+        let ones = (0..REPETITION)
+            .map(|j| i * REPETITION + j)
+            .filter(|&k| bit(helper, k) ^ bit(response, k) == 1)
+            .count();
+        if ones > REPETITION / 2 {
+            secret[i / 8] |= 1 << (i % 8);
+        }
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_recover_with_noise() {
+        let secret = rand::random::<[u8; 16]>();
+        let response = (0..fuzzy_response_len(16)).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+        let helper = fuzzy_commit(&secret, &response).unwrap();
+
+        // flip the most correctable bits: (REPETITION - 1) / 2 in every group
+        let mut noisy = response.clone();
+        for i in 0..16 * 8 {
+            for j in 0..(REPETITION - 1) / 2 {
+                let k = i * REPETITION + j;
+                noisy[k / 8] ^= 1 << (k % 8);
+            }
+        }
+        assert_eq!(fuzzy_recover(&helper, &noisy, 16).unwrap(), secret);
+
+        // one more flip in the first group corrupts the first bit
+        let k = (REPETITION - 1) / 2;
+        noisy[k / 8] ^= 1 << (k % 8);
+        assert_ne!(fuzzy_recover(&helper, &noisy, 16).unwrap(), secret);
+    }
+}
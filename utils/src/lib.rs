@@ -1,4 +1,10 @@
-use blake2::{Blake2b512, Digest};
+mod channel;
+mod crt;
+mod fuzzy;
+mod pairing;
+mod puf;
+
+use blake2::{Blake2b512, Blake2bMac512, Digest, digest::Mac};
 use blstrs_plus::G1Affine;
 use blstrs_plus::Scalar;
 use rand::{RngCore, SeedableRng};
@@ -9,6 +15,12 @@ use rug::{
     integer::{IsPrime, Order},
 };
 
+pub use channel::*;
+pub use crt::*;
+pub use fuzzy::*;
+pub use pairing::*;
+pub use puf::*;
+
 const BIT_LENGTH: usize = 256;
 
 pub fn hash_to_prime(data: String) -> Integer {
@@ -106,6 +118,39 @@ pub fn derive_session_key_from_g1(point: &G1Affine) -> [u8; 16] {
     key
 }
 
+/// Key-check value letting group members confirm they derived the same `k_d`.
+pub fn key_check_value(group_id: &str, epoch: u64, kd: &Integer) -> [u8; 16] {
+    let mut hasher = Blake2b512::new();
+    hasher.update(b"egcda/kcv");
+    hasher.update(group_id.as_bytes());
+    hasher.update(epoch.to_be_bytes());
+    hasher.update(kd.to_digits::<u8>(Order::MsfBe));
+    let digest = hasher.finalize();
+    let mut kcv = [0u8; 16];
+    kcv.copy_from_slice(&digest[..16]);
+    kcv
+}
+
+/// Keyed BLAKE2b MAC over `parts` under a session key; each part is length-prefixed, so no two
+/// splits of the same bytes share a tag.
+pub fn session_mac(key: &[u8; 16], parts: &[&[u8]]) -> Vec<u8> {
+    session_mac_state(key, parts).finalize().into_bytes().to_vec()
+}
+
+/// Constant-time check of a tag produced by [`session_mac`].
+pub fn verify_session_mac(key: &[u8; 16], parts: &[&[u8]], tag: &[u8]) -> bool {
+    session_mac_state(key, parts).verify_slice(tag).is_ok()
+}
+
+fn session_mac_state(key: &[u8; 16], parts: &[&[u8]]) -> Blake2bMac512 {
+    let mut mac = <Blake2bMac512 as Mac>::new_from_slice(key).expect("BLAKE2b MAC key length is valid");
+    for part in parts {
+        mac.update(&(part.len() as u64).to_be_bytes());
+        mac.update(part);
+    }
+    mac
+}
+
 /// Abbreviate a string like: aa9f0...34865
 ///
 /// Keeps the first `head` characters and last `tail` characters, inserting
@@ -146,6 +191,17 @@ mod tests {
         assert!(prime.is_probably_prime(10) != IsPrime::No);
     }
 
+    #[test]
+    fn test_session_mac() {
+        let key = [3u8; 16];
+        let tag = session_mac(&key, &[b"uid", b"payload"]);
+        assert!(verify_session_mac(&key, &[b"uid", b"payload"], &tag));
+        assert!(!verify_session_mac(&key, &[b"uid", b"tampered"], &tag));
+        assert!(!verify_session_mac(&[4u8; 16], &[b"uid", b"payload"], &tag));
+        assert!(!verify_session_mac(&key, &[b"uidpay", b"load"], &tag));
+        assert!(!verify_session_mac(&key, &[b"uidpayload"], &tag));
+    }
+
     #[test]
     fn test_crt() {
         let mut p = Vec::new();
@@ -0,0 +1,115 @@
+use blake2::Blake2b512;
+use blstrs_plus::{
+    G1Affine, G1Projective, G2Affine, G2Prepared, G2Projective, Scalar,
+    elliptic_curve::{
+        hash2curve::ExpandMsgXmd,
+        subtle::{ConditionallySelectable, ConstantTimeEq},
+    },
+    group::{Curve, Group, prime::PrimeCurveAffine},
+    multi_miller_loop,
+    pairing_lib::MillerLoopResult,
+};
+use std::sync::LazyLock;
+
+/// Domain separation tag of BLS signatures in G2 under keys in G1.
+pub const G2_SIG_TAG: &[u8] = b"BLS_SIG_BLS12381G2_XMD:BLAKE2b-512_SSWU_RO_NUL_";
+
+/// Window width in bits of the fixed-base table of the G1 generator.
+pub const G1_GENERATOR_WINDOW: usize = 5;
+
+static G1_GENERATOR_TABLE: LazyLock<FixedBaseG1<G1_GENERATOR_WINDOW>> = LazyLock::new(|| FixedBaseG1::new(G1Projective::generator()));
+
+static G2_GENERATOR_PREPARED: LazyLock<G2Prepared> = LazyLock::new(|| G2Prepared::from(G2Affine::generator()));
+
+/// Fixed-base comb for one G1 point: window `i` holds `j * 2^(W i) * base` for every `W`-bit digit `j`.
+///
+/// A multiplication is one addition per window and no doublings. Entries are selected in constant
+/// time, so secret scalars such as nonces do not leak through the table access pattern.
+pub struct FixedBaseG1<const W: usize> {
+    windows: Vec<Vec<G1Affine>>,
+}
+
+impl<const W: usize> FixedBaseG1<W> {
+    pub fn new(base: G1Projective) -> Self {
+        let mut windows = Vec::with_capacity((Scalar::BYTES * 8).div_ceil(W));
+        let mut base = base;
+        for _ in 0..windows.capacity() {
+            let mut row = Vec::with_capacity(1 << W);
+            let mut acc = G1Projective::identity();
+            for _ in 0..1 << W {
+                row.push(acc);
+                acc += base;
+            }
+            let mut affine = vec![G1Affine::identity(); row.len()];
+            G1Projective::batch_normalize(&row, &mut affine);
+            windows.push(affine);
+            base = acc;
+        }
+        Self { windows }
+    }
+
+    pub fn mul(&self, k: &Scalar) -> G1Projective {
+        let bytes = k.to_le_bytes();
+        let bit = |i: usize| bytes.get(i / 8).map_or(0, |b| (b >> (i % 8)) & 1) as u64;
+        self.windows.iter().enumerate().fold(G1Projective::identity(), |acc, (i, row)| {
+            let digit = (0..W).map(|j| bit(i * W + j) << j).sum::<u64>();
+            let mut point = G1Affine::identity();
+            for (j, entry) in row.iter().enumerate() {
+                point.conditional_assign(entry, (j as u64).ct_eq(&digit));
+            }
+            acc + point
+        })
+    }
+}
+
+/// `k * g1` using the precomputed fixed-base table of the G1 generator.
+pub fn g1_mul_generator(k: &Scalar) -> G1Projective {
+    G1_GENERATOR_TABLE.mul(k)
+}
+
+/// The G2 generator with its Miller loop lines precomputed.
+pub fn g2_generator_prepared() -> &'static G2Prepared {
+    &G2_GENERATOR_PREPARED
+}
+
+/// Whether `prod e(p_i, q_i) = 1`, with one shared Miller loop and a single final exponentiation.
+pub fn pairing_product_is_identity(terms: &[(&G1Affine, &G2Prepared)]) -> bool {
+    multi_miller_loop(terms).final_exponentiation().is_identity().into()
+}
+
+/// BLS signature in G2 over `msg`, verifiable against `sk * g1`.
+pub fn sign_g2(sk: &Scalar, msg: &[u8]) -> G2Affine {
+    (G2Projective::hash::<ExpandMsgXmd<Blake2b512>>(msg, G2_SIG_TAG) * sk).into()
+}
+
+/// Check `e(pk, H(msg)) = e(g1, sigma)` for a signature made with [`sign_g2`].
+pub fn verify_g2(pk: &G1Affine, msg: &[u8], sigma: &G2Affine) -> bool {
+    let h = G2Affine::from(G2Projective::hash::<ExpandMsgXmd<Blake2b512>>(msg, G2_SIG_TAG));
+    let neg_g1 = -G1Affine::generator();
+    pairing_product_is_identity(&[(pk, &G2Prepared::from(h)), (&neg_g1, &G2Prepared::from(*sigma))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blstrs_plus::ff::Field;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_fixed_base_matches_mul() {
+        let table = FixedBaseG1::<5>::new(G1Projective::generator());
+        for k in [Scalar::ZERO, Scalar::ONE, -Scalar::ONE, Scalar::random(OsRng)] {
+            assert_eq!(table.mul(&k), G1Projective::generator() * k);
+            assert_eq!(g1_mul_generator(&k), G1Projective::generator() * k);
+        }
+    }
+
+    #[test]
+    fn test_sign_g2_roundtrip() {
+        let sk = Scalar::random(OsRng);
+        let pk = G1Affine::from(G1Projective::generator() * sk);
+        let sigma = sign_g2(&sk, b"gs certificate");
+        assert!(verify_g2(&pk, b"gs certificate", &sigma));
+        assert!(!verify_g2(&pk, b"gs certificate!", &sigma));
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Result, bail};
+use std::{fmt, str::FromStr};
+
+/// Widest challenge or response a geometry may describe; bounded by the serial frame payload and
+/// the challenge field of the GS records.
+pub const MAX_PUF_BITS: usize = 256;
+
+/// Text form of a response, which goes into the protocol hashes as is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PufEncoding {
+    /// Upper-case hex, as `puf_py` and `puf_sim` answer.
+    #[default]
+    HexUpper,
+    HexLower,
+}
+
+impl PufEncoding {
+    fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Self::HexUpper => hex::encode_upper(bytes),
+            Self::HexLower => hex::encode(bytes),
+        }
+    }
+
+    fn accepts(self, text: &str) -> bool {
+        match self {
+            Self::HexUpper => !text.bytes().any(|b| b.is_ascii_lowercase()),
+            Self::HexLower => !text.bytes().any(|b| b.is_ascii_uppercase()),
+        }
+    }
+}
+
+impl FromStr for PufEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hex-upper" => Ok(Self::HexUpper),
+            "hex-lower" => Ok(Self::HexLower),
+            _ => bail!("Unknown PUF response encoding: {s} (expected hex-upper or hex-lower)"),
+        }
+    }
+}
+
+impl fmt::Display for PufEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::HexUpper => "hex-upper",
+            Self::HexLower => "hex-lower",
+        })
+    }
+}
+
+/// Challenge and response widths of a PUF and the text form of its responses.
+///
+/// Part of the enrollment record of a UAV: the TA issues challenges of `challenge_bits` and checks
+/// responses against it. Challenges travel as hex of either case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PufGeometry {
+    pub challenge_bits: usize,
+    pub response_bits: usize,
+    pub encoding: PufEncoding,
+}
+
+impl Default for PufGeometry {
+    /// 96-bit challenges and responses, the width of `puf_py` and the `puf_uart` bitstream.
+    fn default() -> Self {
+        Self {
+            challenge_bits: 96,
+            response_bits: 96,
+            encoding: PufEncoding::HexUpper,
+        }
+    }
+}
+
+impl fmt::Display for PufGeometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-bit challenges, {}-bit {} responses",
+            self.challenge_bits, self.response_bits, self.encoding
+        )
+    }
+}
+
+impl PufGeometry {
+    /// Both widths must be whole bytes within [`MAX_PUF_BITS`].
+    pub fn validate(&self) -> Result<()> {
+        for (name, bits) in [("challenge", self.challenge_bits), ("response", self.response_bits)] {
+            if bits == 0 || bits > MAX_PUF_BITS || !bits.is_multiple_of(8) {
+                bail!("PUF {name} width must be a multiple of 8 bits up to {MAX_PUF_BITS}, got {bits}");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn challenge_bytes(&self) -> usize {
+        self.challenge_bits / 8
+    }
+
+    pub fn response_bytes(&self) -> usize {
+        self.response_bits / 8
+    }
+
+    pub fn decode_challenge(&self, challenge: &str) -> Result<Vec<u8>> {
+        let bytes = hex::decode(challenge)?;
+        if bytes.len() != self.challenge_bytes() {
+            bail!(
+                "PUF challenge must be {} hex digits, got {}",
+                self.challenge_bytes() * 2,
+                challenge.len()
+            );
+        }
+        Ok(bytes)
+    }
+
+    /// Bytes of a response in the text form of [`PufGeometry::encoding`].
+    pub fn decode_response(&self, response: &str) -> Result<Vec<u8>> {
+        let bytes = hex::decode(response)?;
+        if bytes.len() != self.response_bytes() {
+            bail!(
+                "PUF response must be {} hex digits, got {}",
+                self.response_bytes() * 2,
+                response.len()
+            );
+        }
+        if !self.encoding.accepts(response) {
+            bail!("PUF response is not {}: {response}", self.encoding);
+        }
+        Ok(bytes)
+    }
+
+    pub fn encode_response(&self, response: &[u8]) -> String {
+        self.encoding.encode(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geometry_checks_widths_and_encoding() {
+        let g = PufGeometry {
+            challenge_bits: 64,
+            response_bits: 256,
+            encoding: PufEncoding::HexLower,
+        };
+        g.validate().unwrap();
+        assert_eq!(g.decode_challenge("00112233AABBCCDD").unwrap().len(), 8);
+        assert!(g.decode_challenge("00112233445566778899aabb").is_err());
+        let r = g.encode_response(&[0xAB; 32]);
+        assert_eq!(g.decode_response(&r).unwrap(), [0xAB; 32]);
+        assert!(g.decode_response(&r.to_uppercase()).is_err());
+        assert!(PufGeometry { challenge_bits: 100, ..g }.validate().is_err());
+        assert!(PufGeometry { response_bits: 512, ..g }.validate().is_err());
+    }
+}